@@ -0,0 +1,57 @@
+use parser::CellWidth;
+
+/// Integer the tape is made of, see [`CellWidth`] for how it wraps around.
+pub trait Cell: Copy + Default + Eq + std::fmt::Debug {
+    const WIDTH: CellWidth;
+
+    /// Converts a value from the IR.
+    fn from_value(value: i32) -> Self;
+
+    fn from_byte(byte: u8) -> Self;
+
+    /// Only the low byte is printed.
+    fn to_byte(self) -> u8;
+
+    fn to_value(self) -> u32;
+
+    fn add(self, value: i32) -> Self;
+
+    /// `self + cell * value`.
+    fn mul_add(self, cell: Self, value: i32) -> Self;
+}
+
+macro_rules! cell {
+    ($ty:ty, $width:expr) => {
+        impl Cell for $ty {
+            const WIDTH: CellWidth = $width;
+
+            fn from_value(value: i32) -> Self {
+                value as $ty
+            }
+
+            fn from_byte(byte: u8) -> Self {
+                byte as $ty
+            }
+
+            fn to_byte(self) -> u8 {
+                self as u8
+            }
+
+            fn to_value(self) -> u32 {
+                self as u32
+            }
+
+            fn add(self, value: i32) -> Self {
+                self.wrapping_add(value as $ty)
+            }
+
+            fn mul_add(self, cell: Self, value: i32) -> Self {
+                self.wrapping_add(cell.wrapping_mul(value as $ty))
+            }
+        }
+    };
+}
+
+cell!(u8, CellWidth::U8);
+cell!(u16, CellWidth::U16);
+cell!(u32, CellWidth::U32);
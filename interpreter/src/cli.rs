@@ -1,8 +1,161 @@
-use std::path::PathBuf;
+use std::{
+    ops::{Range, RangeInclusive},
+    path::PathBuf,
+    time::Duration,
+};
+
+use interpreter::{parse_seconds, parse_source_range, parse_steps, Limits, TraceFormat};
+use parser::{CellWidth, EofPolicy, OptLevel, OutputMode, TapePolicy, DEFAULT_TAPE_SIZE};
 
 #[derive(Debug, Clone, clap::Parser)]
 #[command(version, about = "Runs brainfuck using interpreter.")]
 pub struct Cli {
     #[arg(help = "Path to file with source code")]
     pub source: Option<PathBuf>,
+    #[arg(long, help = "Print parse errors as JSON")]
+    pub json_errors: bool,
+    #[arg(
+        long,
+        default_value = "3",
+        help = "Optimization level, from 0 (no passes) to 3 (all passes)"
+    )]
+    pub opt_level: OptLevel,
+    #[arg(long, help = "Print IR to stderr after every optimization pass")]
+    pub dump_ir: bool,
+    #[arg(
+        long,
+        help = "Print the tape, pointer, step count and exit reason to stderr as JSON once the program stops"
+    )]
+    pub dump_state: bool,
+    #[arg(
+        long,
+        help = "Run the program under a debugger that reads commands from stdin, type 'help' for a list"
+    )]
+    pub debug: bool,
+    #[arg(
+        long,
+        value_name = "STEPS",
+        default_value_t = 1 << 16,
+        requires = "debug",
+        help = "Steps the debugger takes back one at a time, going back further replays the program from a checkpoint"
+    )]
+    pub history: usize,
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "text",
+        conflicts_with = "debug",
+        help = "Count how often every loop runs and print the hottest ones (text), all of them (json), a flame graph (folded) or a Chrome trace (chrome) to stderr once the program stops"
+    )]
+    pub profile: Option<ProfileFormat>,
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "text",
+        conflicts_with = "debug",
+        help = "Write the instruction, pointer and cell before and after of every step as text, jsonl or binary"
+    )]
+    pub trace: Option<TraceFormat>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        requires = "trace",
+        help = "Write the trace to this file instead of stderr"
+    )]
+    pub trace_file: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "FROM..TO",
+        value_parser = parse_steps,
+        requires = "trace",
+        help = "Only trace these steps, counting from 0, like 1000..2000 or 1000.."
+    )]
+    pub trace_steps: Option<Range<u64>>,
+    #[arg(
+        long,
+        value_name = "FROM..TO",
+        value_parser = parse_source_range,
+        requires = "trace",
+        help = "Only trace instructions that start between these lines and columns, like 3..5 or 3:14..5:2"
+    )]
+    pub trace_source: Option<RangeInclusive<(usize, usize)>>,
+    #[arg(
+        long,
+        default_value = "8",
+        help = "Bits per cell (8, 16 or 32), arithmetic wraps around at that width"
+    )]
+    pub cell_width: CellWidth,
+    #[arg(
+        long,
+        default_value = "zero",
+        help = "What ',' does at the end of input: unchanged, zero, minus-one or error"
+    )]
+    pub eof: EofPolicy,
+    #[arg(
+        long,
+        default_value = "bytes",
+        help = "What '.' writes: bytes (the low byte of the cell) or unicode (the cell as a UTF-8 encoded code point)"
+    )]
+    pub output_mode: OutputMode,
+    #[arg(
+        long,
+        default_value_t = DEFAULT_TAPE_SIZE as u32,
+        value_parser = clap::value_parser!(u32).range(1..=i32::MAX as i64),
+        help = "Number of cells on the tape"
+    )]
+    pub tape_size: u32,
+    #[arg(
+        long,
+        default_value = "wrap",
+        help = "What happens when the pointer leaves the tape: wrap, error or grow"
+    )]
+    pub tape_policy: TapePolicy,
+    #[arg(
+        long,
+        value_name = "BYTES",
+        help = "Most memory the tape can grow to with '--tape-policy grow', unlimited by default"
+    )]
+    pub memory_limit: Option<usize>,
+    #[arg(
+        long,
+        value_name = "STEPS",
+        help = "Stop once this many optimized instructions ran, cells a scan moves over count too"
+    )]
+    pub fuel: Option<u64>,
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        value_parser = parse_seconds,
+        help = "Stop once the program ran this long"
+    )]
+    pub timeout: Option<Duration>,
+    #[arg(
+        long,
+        value_name = "BYTES",
+        help = "Stop instead of writing more than this many bytes"
+    )]
+    pub max_output: Option<u64>,
+}
+
+impl Cli {
+    pub fn limits(&self) -> Limits {
+        Limits {
+            fuel: self.fuel,
+            timeout: self.timeout,
+            output: self.max_output,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, clap::ValueEnum)]
+pub enum ProfileFormat {
+    #[default]
+    Text,
+    Json,
+    Folded,
+    Chrome,
 }
@@ -0,0 +1,717 @@
+use std::{
+    fmt::Write as _,
+    io::{Read, Write},
+};
+
+use color_eyre::{
+    eyre::{eyre, Context as _},
+    Result,
+};
+
+use parser::{LineIndex, Span};
+
+use crate::{Cell, Condition, ExitReason, Interpreter, Io, MachineState, Watchpoint};
+
+const HELP: &str = "\
+commands, an empty line runs the last one again:
+  step [N]          (s)   run until the next part of the source, N times
+  stepi [N]         (si)  run N optimized instructions
+  continue          (c)   run until a breakpoint, a watchpoint or the end
+  reverse-step [N]  (rs)  run backwards to the previous part of the source, N times
+  reverse-stepi [N] (rsi) run N optimized instructions backwards
+  reverse-continue  (rc)  run backwards to a breakpoint, a watchpoint or as far as the history goes
+  last-write [POS]  (lw)  run backwards to the last write of the cell at POS, or under the pointer
+  break LINE[:COL]  (b)   stop before the first instruction at or after LINE:COL, add 'if COND'
+                          to only stop there when COND holds
+  watch COND        (w)   stop once COND holds
+  delete ID         (d)   remove a breakpoint or watchpoint
+  info              (i)   list breakpoints and watchpoints
+  tape [RADIUS]     (t)   show the cells around the pointer
+  set [POS] VALUE         set the cell at POS, or under the pointer
+  list [RADIUS]     (l)   show the instructions around the next one, with their source
+  help              (h)   show this
+  quit              (q)   stop debugging
+conditions are 'cell[POS]' for the cell at POS changing, or compare 'ptr', 'cell' (under the
+pointer) or 'cell[POS]' to a number with ==, !=, <, <=, > or >=, like 'ptr > 1000'
+";
+
+/// Cells `tape` shows on either side of the pointer.
+const TAPE_RADIUS: usize = 8;
+/// Instructions `list` shows on either side of the next one.
+const LIST_RADIUS: usize = 5;
+/// Longest source text shown for an instruction, folded loops can be much longer.
+const SOURCE_WIDTH: usize = 32;
+
+/// Runs a program one command at a time, the way `--debug` does.
+///
+/// Commands are read from the same input as the program's `,`, and the debugger's answers go
+/// to the same output as its `.`. Going backwards needs the interpreter to record its history,
+/// see [`Interpreter::with_history`].
+#[derive(Debug, Clone)]
+pub struct Debugger<C: Cell> {
+    interpreter: Interpreter<C>,
+    source: Vec<u8>,
+    lines: LineIndex,
+    /// Where each instruction came from.
+    spans: Vec<Span>,
+}
+
+impl<C: Cell> Debugger<C> {
+    /// Debugs the program `interpreter` runs, parsed from `source`. `spans` says where each of
+    /// its instructions came from.
+    pub fn new(interpreter: Interpreter<C>, source: &[u8], spans: Vec<Span>) -> Self {
+        assert_eq!(
+            interpreter.instructions().len(),
+            spans.len(),
+            "every instruction needs a span"
+        );
+
+        Self {
+            interpreter,
+            source: source.to_vec(),
+            lines: LineIndex::new(source),
+            spans,
+        }
+    }
+
+    pub fn interpreter(&self) -> &Interpreter<C> {
+        &self.interpreter
+    }
+
+    /// Adds a breakpoint before the first instruction at or after `line` and `column`, both
+    /// 1-based, that only stops the program if `condition` holds there. Returns its ID, or an
+    /// error if the source has no such line or the line no such column.
+    pub fn break_at(
+        &mut self,
+        line: usize,
+        column: usize,
+        condition: Option<Condition>,
+    ) -> Result<usize> {
+        if line == 0 || line > self.lines.line_count() {
+            return Err(eyre!("there's no {line}:{column} in the source"));
+        }
+        // an empty line still has its first column
+        let range = self.lines.line_range(&self.source, line);
+        if column == 0 || column > range.len().max(1) {
+            return Err(eyre!("there's no {line}:{column} in the source"));
+        }
+
+        let offset = range.start + column - 1;
+        let instruction = self
+            .spans
+            .iter()
+            .position(|span| span.end > offset)
+            .ok_or_else(|| eyre!("there are no instructions at or after {line}:{column}"))?;
+
+        Ok(self.interpreter.watch(Watchpoint {
+            instruction: Some(instruction),
+            condition,
+        }))
+    }
+
+    /// Reads commands from `io` until `quit` or the end of the input, returning what the
+    /// program left.
+    pub fn run<R: Read, W: Write>(&mut self, io: &mut Io<R, W>) -> Result<MachineState> {
+        let mut last = String::new();
+
+        io.write(self.status().as_bytes())
+            .wrap_err("failed to write output")?;
+
+        loop {
+            io.write(b"(debug) ").wrap_err("failed to write output")?;
+
+            let Some(line) = io.read_line().wrap_err("failed to read command")? else {
+                break;
+            };
+            let line = match line.trim() {
+                "" => last.clone(),
+                line => line.to_string(),
+            };
+
+            let mut words = line.split_whitespace();
+            let Some(command) = words.next() else {
+                continue;
+            };
+            if let "quit" | "q" = command {
+                break;
+            }
+
+            let answer = self
+                .command(command, words.collect(), io)
+                .unwrap_or_else(|err| format!("error: {err:#}\n"));
+            io.write(answer.as_bytes())
+                .wrap_err("failed to write output")?;
+
+            last = line;
+        }
+
+        io.flush().wrap_err("failed to write output")?;
+
+        Ok(self.interpreter.state())
+    }
+
+    /// Runs a single command, returning what to answer.
+    fn command<R: Read, W: Write>(
+        &mut self,
+        command: &str,
+        args: Vec<&str>,
+        io: &mut Io<R, W>,
+    ) -> Result<String> {
+        let arg = |idx: usize, default: usize| -> Result<usize> {
+            args.get(idx).map_or(Ok(default), |arg| {
+                arg.parse()
+                    .map_err(|_| eyre!("expected a number, got '{arg}'"))
+            })
+        };
+
+        match command {
+            "step" | "s" => {
+                for _ in 0..arg(0, 1)? {
+                    let start = self.span().map(|span| span.start);
+                    let spans = &self.spans;
+
+                    let stopped = Self::run_until(&mut self.interpreter, io, |interpreter| {
+                        spans.get(interpreter.instptr()).map(|span| span.start) != start
+                    })?;
+                    if !stopped {
+                        break;
+                    }
+                }
+
+                Ok(self.stopped_at())
+            }
+            "stepi" | "si" => {
+                let count = arg(0, 1)?;
+                let mut steps = 0;
+
+                if count != 0 {
+                    Self::run_until(&mut self.interpreter, io, |_| {
+                        steps += 1;
+                        steps == count
+                    })?;
+                }
+
+                Ok(self.stopped_at())
+            }
+            "continue" | "c" => {
+                Self::run_until(&mut self.interpreter, io, |_| false)?;
+
+                Ok(self.stopped_at())
+            }
+            "reverse-step" | "rs" => {
+                for _ in 0..arg(0, 1)? {
+                    let start = self.span().map(|span| span.start);
+                    let spans = &self.spans;
+
+                    let found = self.interpreter.rewind_until(|interpreter| {
+                        spans.get(interpreter.instptr()).map(|span| span.start) != start
+                    })?;
+                    if !found {
+                        return self.rewind_all();
+                    }
+                }
+
+                Ok(self.status())
+            }
+            "reverse-stepi" | "rsi" => {
+                let count = arg(0, 1)? as u64;
+
+                match self.interpreter.rewind(count)? < count {
+                    true => self.rewind_all(),
+                    false => Ok(self.status()),
+                }
+            }
+            "reverse-continue" | "rc" => {
+                let mut hit = None;
+
+                self.interpreter.rewind_until(|interpreter| {
+                    hit = interpreter
+                        .watchpoints()
+                        .find(|(_, watchpoint)| {
+                            watchpoint.matches(interpreter)
+                                && match watchpoint.condition {
+                                    // going backwards, a cell changes right before it is written
+                                    Some(Condition::Changed(position)) => {
+                                        interpreter.target() == Some(position)
+                                    }
+                                    _ => true,
+                                }
+                        })
+                        .map(|(id, _)| id);
+
+                    hit.is_some()
+                })?;
+
+                match hit {
+                    Some(id) => Ok(format!("{}, {}", self.describe_hit(id), self.status())),
+                    None => self.rewind_all(),
+                }
+            }
+            "last-write" | "lw" => {
+                let position = match args.first() {
+                    Some(position) => parse_position(position)?,
+                    None => self.interpreter.memptr(),
+                };
+
+                match self.interpreter.rewind_to_write(position)? {
+                    true => Ok(self.status()),
+                    false => Err(eyre!(
+                        "cell {position} wasn't written as far as the history goes"
+                    )),
+                }
+            }
+            "break" | "b" => {
+                let location = args
+                    .first()
+                    .ok_or_else(|| eyre!("expected a location like 3 or 3:14"))?;
+                let (line, column) = location.split_once(':').unwrap_or((location, "1"));
+                let parse = |value: &str| {
+                    value
+                        .parse()
+                        .map_err(|_| eyre!("expected a location like 3 or 3:14, got '{location}'"))
+                };
+                let condition = match args.get(1) {
+                    Some(&"if") => Some(args[2..].join(" ").parse()?),
+                    Some(word) => {
+                        return Err(eyre!("expected 'if' after the location, got '{word}'"))
+                    }
+                    None => None,
+                };
+
+                let id = self.break_at(parse(line)?, parse(column)?, condition)?;
+
+                Ok(format!("{}\n", self.describe(id)))
+            }
+            "watch" | "w" => {
+                let condition = args.join(" ").parse()?;
+                let id = self.interpreter.watch(Watchpoint {
+                    instruction: None,
+                    condition: Some(condition),
+                });
+
+                Ok(format!("{}\n", self.describe(id)))
+            }
+            "delete" | "d" => {
+                let id = args
+                    .first()
+                    .ok_or_else(|| eyre!("expected a breakpoint or watchpoint ID"))
+                    .and_then(|_| arg(0, 0))?;
+                match self.interpreter.unwatch(id) {
+                    Some(Watchpoint {
+                        instruction: Some(_),
+                        ..
+                    }) => Ok(format!("deleted breakpoint {id}\n")),
+                    Some(_) => Ok(format!("deleted watchpoint {id}\n")),
+                    None => Err(eyre!("there's no breakpoint or watchpoint {id}")),
+                }
+            }
+            "info" | "i" => {
+                let mut out = String::new();
+
+                // writing to a `String` never fails
+                for (id, _) in self.interpreter.watchpoints() {
+                    writeln!(out, "{}", self.describe(id)).unwrap();
+                }
+
+                if out.is_empty() {
+                    out.push_str("no breakpoints or watchpoints\n");
+                }
+
+                Ok(out)
+            }
+            "tape" | "t" => Ok(self.tape(arg(0, TAPE_RADIUS)?)),
+            "set" => {
+                let (position, value) = match args[..] {
+                    [value] => (self.interpreter.memptr(), value),
+                    [position, value] => (parse_position(position)?, value),
+                    _ => return Err(eyre!("expected a value, and maybe a cell position first")),
+                };
+                let value = value
+                    .parse::<i64>()
+                    .map_err(|_| eyre!("expected a value, got '{value}'"))?;
+
+                self.interpreter.set_cell(position, value as u32)?;
+
+                Ok(format!(
+                    "cell {position} is {}\n",
+                    self.interpreter.tape()[position].to_value()
+                ))
+            }
+            "list" | "l" => Ok(self.list(arg(0, LIST_RADIUS)?)),
+            "help" | "h" => Ok(HELP.to_string()),
+            _ => Err(eyre!("unknown command '{command}', try 'help'")),
+        }
+    }
+
+    /// Same as [`Interpreter::run_until`], but an error the program stopped with is only
+    /// remembered, so it can still be looked at.
+    fn run_until<R: Read, W: Write>(
+        interpreter: &mut Interpreter<C>,
+        io: &mut Io<R, W>,
+        predicate: impl FnMut(&Interpreter<C>) -> bool,
+    ) -> Result<bool> {
+        let result = interpreter.run_until(io, predicate);
+
+        match interpreter.state().exit {
+            Some(ExitReason::Error(_)) => Ok(false),
+            _ => result,
+        }
+    }
+
+    /// Status, saying which breakpoint or watchpoint stopped the program if one did.
+    fn stopped_at(&self) -> String {
+        match self.interpreter.watchpoint_hit() {
+            Some(id) => format!("{}, {}", self.describe_hit(id), self.status()),
+            None => self.status(),
+        }
+    }
+
+    /// A breakpoint or watchpoint, with where it stops.
+    fn describe(&self, id: usize) -> String {
+        let (_, watchpoint) = self
+            .interpreter
+            .watchpoints()
+            .find(|&(at, _)| at == id)
+            .expect("there's a watchpoint with this ID");
+
+        match watchpoint.instruction {
+            Some(instruction) => {
+                let condition = match watchpoint.condition {
+                    Some(condition) => format!(" if {condition}"),
+                    None => String::new(),
+                };
+
+                format!(
+                    "breakpoint {id} at instruction {instruction} ({}){condition}",
+                    self.spans[instruction]
+                )
+            }
+            None => format!("watchpoint {id}: {watchpoint}"),
+        }
+    }
+
+    /// How the breakpoint or watchpoint that stopped the program is named in the status.
+    fn describe_hit(&self, id: usize) -> String {
+        match self.interpreter.watchpoints().find(|&(at, _)| at == id) {
+            Some((_, watchpoint)) if watchpoint.instruction.is_none() => {
+                format!("watchpoint {id} ({watchpoint})")
+            }
+            _ => format!("breakpoint {id}"),
+        }
+    }
+
+    /// Goes back as far as the history goes.
+    fn rewind_all(&mut self) -> Result<String> {
+        self.interpreter.rewind(u64::MAX)?;
+
+        Ok(format!("no more history, {}", self.status()))
+    }
+
+    /// Span of the instruction that runs next.
+    fn span(&self) -> Option<Span> {
+        self.spans.get(self.interpreter.instptr()).copied()
+    }
+
+    /// Where the program is, or why it stopped.
+    fn status(&self) -> String {
+        let steps = self.interpreter.steps;
+
+        match self.interpreter.state().exit {
+            Some(ExitReason::Halted) => format!("program halted after {steps} steps\n"),
+            Some(ExitReason::Error(message)) => {
+                format!("program stopped after {steps} steps: {message}\n")
+            }
+            Some(ExitReason::LimitExceeded(limit)) => {
+                format!("program stopped after {steps} steps: {limit}\n")
+            }
+            None => {
+                let instptr = self.interpreter.instptr();
+
+                format!(
+                    "next is instruction {instptr} ({}): {:?} {}\n",
+                    self.spans[instptr],
+                    self.interpreter.instructions()[instptr],
+                    self.text(instptr)
+                )
+            }
+        }
+    }
+
+    /// Commands the instruction at `idx` came from, without comments and shortened if there
+    /// are many.
+    fn text(&self, idx: usize) -> String {
+        let mut text = self.source[self.spans[idx].range()]
+            .iter()
+            .filter(|byte| b"+-<>[],.".contains(byte))
+            .map(|&byte| byte as char)
+            .collect::<String>();
+
+        if text.len() > SOURCE_WIDTH {
+            text.truncate(SOURCE_WIDTH);
+            text.push_str("...");
+        }
+
+        text
+    }
+
+    /// Cells up to `radius` away from the pointer, as a row of positions over a row of values,
+    /// with a mark under the pointer.
+    fn tape(&self, radius: usize) -> String {
+        let tape = self.interpreter.tape();
+        let memptr = self.interpreter.memptr();
+        let positions = tape.positions();
+        // a radius past the whole tape shows the same, and can't overflow
+        let radius = radius.min(tape.cells().len()) as isize;
+        let start = (memptr - radius).max(positions.start);
+        let end = (memptr + radius + 1).min(positions.end);
+
+        let (mut header, mut values, mut marks) = (String::new(), String::new(), String::new());
+        for position in start..end {
+            let value = tape[position].to_value();
+            let width = position.to_string().len().max(value.to_string().len()) + 1;
+
+            // writing to a `String` never fails
+            write!(header, "{position:>width$}").unwrap();
+            write!(values, "{value:>width$}").unwrap();
+            let mark = if position == memptr { "^" } else { "" };
+            write!(marks, "{mark:>width$}").unwrap();
+        }
+
+        format!("{header}\n{values}\n{}\n", marks.trim_end())
+    }
+
+    /// Instructions up to `radius` away from the one that runs next, next to their source.
+    fn list(&self, radius: usize) -> String {
+        let instptr = self.interpreter.instptr();
+        let start = instptr.saturating_sub(radius);
+        let end = instptr
+            .saturating_add(radius)
+            .saturating_add(1)
+            .min(self.spans.len());
+
+        let mut out = String::new();
+        for idx in start..end {
+            let marker = match (
+                idx == instptr,
+                self.interpreter
+                    .watchpoints()
+                    .any(|(_, watchpoint)| watchpoint.instruction == Some(idx)),
+            ) {
+                (true, _) => "=>",
+                (false, true) => " *",
+                (false, false) => "  ",
+            };
+            let instruction = format!("{:?}", self.interpreter.instructions()[idx]);
+            let location = self.spans[idx].to_string();
+
+            // writing to a `String` never fails
+            writeln!(
+                out,
+                "{marker}{idx:>6}  {location:<10} {instruction:<24} {}",
+                self.text(idx)
+            )
+            .unwrap();
+        }
+
+        if out.is_empty() {
+            out.push_str("no instructions\n");
+        }
+
+        out
+    }
+}
+
+fn parse_position(position: &str) -> Result<isize> {
+    position
+        .parse()
+        .map_err(|_| eyre!("expected a cell position, got '{position}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Tape;
+    use parser::{OptimizationOptions, Parser, TapePolicy};
+
+    fn debug(source: &[u8], commands: &str) -> String {
+        debug_with(source, Tape::default(), commands)
+    }
+
+    fn debug_with(source: &[u8], tape: Tape<u8>, commands: &str) -> String {
+        let (instructions, spans) = Parser::new(source)
+            .parse_spanned(OptimizationOptions::all())
+            .expect("failed to parse")
+            .into_iter()
+            .map(|instruction| (instruction.value, instruction.span))
+            .unzip();
+
+        let interpreter = Interpreter::new(instructions)
+            .with_tape(tape)
+            .with_history(4);
+        let mut debugger = Debugger::new(interpreter, source, spans);
+        let mut io = Io::new(commands.as_bytes(), Vec::new());
+        debugger.run(&mut io).expect("failed to debug");
+
+        String::from_utf8(io.into_output().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn step() {
+        let output = debug(b"++\n>+++.", "s\n\nsi 2\ns 5\n");
+
+        assert_eq!(
+            output,
+            "next is instruction 0 (1:1): Add(0, 2) ++\n\
+             (debug) next is instruction 1 (2:2): Add(1, 3) +++\n\
+             (debug) next is instruction 2 (2:5): Out(1) .\n\
+             (debug) \x03program halted after 4 steps\n\
+             (debug) program halted after 4 steps\n\
+             (debug) "
+        );
+    }
+
+    #[test]
+    fn breakpoints() {
+        let output = debug(b"+[>+<-]\n>.", "b 2\nb 1:4\ni\nc\nc\nd 2\nc\nq\n");
+
+        assert_eq!(
+            output,
+            "next is instruction 0 (1:1): Add(0, 1) +\n\
+             (debug) breakpoint 1 at instruction 3 (2:2)\n\
+             (debug) breakpoint 2 at instruction 1 (1:2)\n\
+             (debug) breakpoint 1 at instruction 3 (2:2)\n\
+             breakpoint 2 at instruction 1 (1:2)\n\
+             (debug) breakpoint 2, next is instruction 1 (1:2): Multiply(0, 1, 1) [>+<-]\n\
+             (debug) breakpoint 1, next is instruction 3 (2:2): Out(1) .\n\
+             (debug) deleted breakpoint 2\n\
+             (debug) \x01program halted after 5 steps\n\
+             (debug) "
+        );
+
+        let output = debug(b"+[>+<-]\n\n>.", "b 1:8\nb 3\n");
+        assert_eq!(
+            output,
+            "next is instruction 0 (1:1): Add(0, 1) +\n\
+             (debug) error: there's no 1:8 in the source\n\
+             (debug) breakpoint 1 at instruction 3 (3:2)\n\
+             (debug) "
+        );
+    }
+
+    #[test]
+    fn tape() {
+        let output = debug(b"+>++>+++<", "c\nt 1\nset 0 300\nset 7\nset -1 0\nt\n");
+
+        assert_eq!(
+            output,
+            "next is instruction 0 (1:1): Add(0, 1) +\n\
+             (debug) program halted after 4 steps\n\
+             (debug)  0 1 2\n 1 2 3\n   ^\n\
+             (debug) cell 0 is 44\n\
+             (debug) cell 1 is 7\n\
+             (debug) error: cell -1 isn't on the tape\n\
+             (debug)   0 1 2 3 4 5 6 7 8 9\n 44 7 3 0 0 0 0 0 0 0\n    ^\n\
+             (debug) "
+        );
+
+        let tape = || Tape::new(4, TapePolicy::Wrap);
+        assert_eq!(
+            debug_with(
+                b"+>++",
+                tape(),
+                "t 9223372036854775807\nt 18446744073709551615\n"
+            ),
+            debug_with(b"+>++", tape(), "t 4\nt 4\n")
+        );
+    }
+
+    #[test]
+    fn list() {
+        let output = debug(b"+>[-]<.", "l 1\nl\n");
+
+        assert_eq!(
+            output,
+            "next is instruction 0 (1:1): Add(0, 1) +\n\
+             (debug) =>     0  1:1        Add(0, 1)                +\n       \
+             1  1:3        Clear(1)                 [-]\n\
+             (debug) =>     0  1:1        Add(0, 1)                +\n       \
+             1  1:3        Clear(1)                 [-]\n       \
+             2  1:7        Out(0)                   .\n\
+             (debug) "
+        );
+
+        assert_eq!(
+            debug(b"+>[-]<.", "l 18446744073709551615\n"),
+            debug(b"+>[-]<.", "l 100\n")
+        );
+    }
+
+    #[test]
+    fn error() {
+        let output = debug_with(b"+<", Tape::new(4, TapePolicy::Error), "c\nc\nt 0\nfoo\n");
+
+        assert_eq!(
+            output,
+            "next is instruction 0 (1:1): Add(0, 1) +\n\
+             (debug) program stopped after 1 steps: pointer moved out of the tape to cell -1\n\
+             (debug) program stopped after 1 steps: pointer moved out of the tape to cell -1\n\
+             (debug)  0\n 1\n ^\n\
+             (debug) error: unknown command 'foo', try 'help'\n\
+             (debug) "
+        );
+    }
+
+    #[test]
+    fn reverse() {
+        let output = debug(
+            b"+++\n>++[<+>-]\n<.",
+            "c\nlw 1\nlw 0\nrsi\nrs\nrs\nlw 5\nb 2:4\nc\nc\nrc\n",
+        );
+
+        assert_eq!(
+            output,
+            "next is instruction 0 (1:1): Add(0, 3) +++\n\
+             (debug) \x05program halted after 5 steps\n\
+             (debug) next is instruction 3 (2:4): Clear(1) [<+>-]\n\
+             (debug) next is instruction 2 (2:4): Multiply(1, 0, 1) [<+>-]\n\
+             (debug) next is instruction 1 (2:2): Add(1, 2) ++\n\
+             (debug) next is instruction 0 (1:1): Add(0, 3) +++\n\
+             (debug) no more history, next is instruction 0 (1:1): Add(0, 3) +++\n\
+             (debug) error: cell 5 wasn't written as far as the history goes\n\
+             (debug) breakpoint 1 at instruction 2 (2:4)\n\
+             (debug) breakpoint 1, next is instruction 2 (2:4): Multiply(1, 0, 1) [<+>-]\n\
+             (debug) program halted after 5 steps\n\
+             (debug) breakpoint 1, next is instruction 2 (2:4): Multiply(1, 0, 1) [<+>-]\n\
+             (debug) "
+        );
+    }
+
+    #[test]
+    fn watchpoints() {
+        let output = debug(
+            b"+++\n>++[<+>-]\n<.",
+            "w cell[0]\nw cell[1] < 2\nb 2:4 if cell[1] == 2\nb 2:4 if cell > 3\ni\nc\nc\nc\nd 1\nc\nrc\nw ptr\n",
+        );
+
+        assert_eq!(
+            output,
+            "next is instruction 0 (1:1): Add(0, 3) +++\n\
+             (debug) watchpoint 1: cell[0] changes\n\
+             (debug) watchpoint 2: cell[1] < 2\n\
+             (debug) breakpoint 3 at instruction 2 (2:4) if cell[1] == 2\n\
+             (debug) breakpoint 4 at instruction 2 (2:4) if cell > 3\n\
+             (debug) watchpoint 1: cell[0] changes\n\
+             watchpoint 2: cell[1] < 2\n\
+             breakpoint 3 at instruction 2 (2:4) if cell[1] == 2\n\
+             breakpoint 4 at instruction 2 (2:4) if cell > 3\n\
+             (debug) watchpoint 1 (cell[0] changes), next is instruction 1 (2:2): Add(1, 2) ++\n\
+             (debug) breakpoint 3, next is instruction 2 (2:4): Multiply(1, 0, 1) [<+>-]\n\
+             (debug) watchpoint 1 (cell[0] changes), next is instruction 3 (2:4): Clear(1) [<+>-]\n\
+             (debug) deleted watchpoint 1\n\
+             (debug) watchpoint 2 (cell[1] < 2), next is instruction 4 (3:2): Out(0) .\n\
+             (debug) breakpoint 3, next is instruction 2 (2:4): Multiply(1, 0, 1) [<+>-]\n\
+             (debug) error: expected 'cell[N]' or a comparison like 'ptr > N', got 'ptr'\n\
+             (debug) "
+        );
+    }
+}
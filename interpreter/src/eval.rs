@@ -0,0 +1,343 @@
+use std::time::Instant;
+
+use color_eyre::Result;
+
+use parser::{CellWidth, Instruction, OutputMode, TapePolicy};
+
+use crate::{Cell, Interpreter, Limits, StepResult, Tape};
+
+/// Start of a program that was already run at compile time.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Prefix {
+    /// Everything the prefix printed, already encoded the way the output mode says.
+    pub output: Vec<u8>,
+    /// Tape right after the prefix, every value fits into the cell width. It is longer than
+    /// the tape size if the prefix grew the tape.
+    pub memory: Vec<u32>,
+    pub pointer: usize,
+    /// Instructions the prefix ran, not counting the ones that were rolled back.
+    pub steps: u64,
+    /// The part of the program that still has to run, with jumps relative to its start.
+    pub rest: Vec<Instruction>,
+    /// What is left of the limits for `rest`, after the fuel, time and output the prefix used.
+    pub limits: Limits,
+}
+
+/// Runs the program until it wants to read input or `budget` instructions were run, whichever
+/// comes first. A `MoveUntilZero` counts every cell it moves over as well.
+///
+/// Top-level loops are only taken as a whole, a loop that reads input or runs out of budget is
+/// left for the compiled program, along with everything after it. The same goes for anything
+/// that leaves the tape under [`TapePolicy::Error`] or reaches one of `limits`, so the
+/// compiled program reports it.
+pub fn evaluate_prefix(
+    instructions: Vec<Instruction>,
+    width: CellWidth,
+    tape_size: usize,
+    tape: TapePolicy,
+    output: OutputMode,
+    limits: Limits,
+    budget: usize,
+) -> Result<Prefix> {
+    match width {
+        CellWidth::U8 => evaluate::<u8>(instructions, tape_size, tape, output, limits, budget),
+        CellWidth::U16 => evaluate::<u16>(instructions, tape_size, tape, output, limits, budget),
+        CellWidth::U32 => evaluate::<u32>(instructions, tape_size, tape, output, limits, budget),
+    }
+}
+
+fn evaluate<C: Cell>(
+    instructions: Vec<Instruction>,
+    tape_size: usize,
+    tape: TapePolicy,
+    mode: OutputMode,
+    limits: Limits,
+    budget: usize,
+) -> Result<Prefix> {
+    let started = Instant::now();
+    let mut interpreter = Interpreter::<C>::new(instructions)
+        .with_tape(Tape::new(tape_size, tape).right_only())
+        .with_limits(limits);
+    let mut output = Vec::new();
+    let mut buffer = [0; 4];
+    let mut steps = 0;
+    let mut start = 0;
+
+    while start < interpreter.instructions.len() {
+        let end = match interpreter.instructions[start] {
+            Instruction::JumpIfZero(end) => end + 1,
+            _ => start + 1,
+        };
+
+        // single instructions are checked up front, only loops can have written to the tape by
+        // the time they stop. a scan can stop halfway through, at a limit
+        let snapshot = (end - start > 1).then(|| interpreter.memory.clone());
+        let (memptr, steps_before, scanned) =
+            (interpreter.memptr, interpreter.steps, interpreter.scanned);
+        let printed = output.len();
+
+        interpreter.instptr = start;
+        while interpreter.instptr < end && steps < budget {
+            let cost = match interpreter.instructions[interpreter.instptr] {
+                Instruction::In(_) => break,
+                // a scan is a single step, but on a wrapping tape it might never stop
+                Instruction::MoveUntilZero(n) => match scan(&interpreter, n, budget - steps - 1) {
+                    Some(moves) => moves + 1,
+                    None => break,
+                },
+                _ => 1,
+            };
+
+            // nothing is read, so the only way to stop is leaving the tape or a limit
+            match interpreter.step() {
+                Ok(StepResult::Output(value)) => {
+                    output.extend_from_slice(mode.encode(value, &mut buffer));
+                }
+                Ok(StepResult::LimitExceeded(_)) | Err(_) => break,
+                Ok(_) => {}
+            }
+            steps += cost;
+        }
+
+        if interpreter.instptr < end {
+            if let Some(memory) = snapshot {
+                interpreter.memory = memory;
+            }
+            interpreter.memptr = memptr;
+            interpreter.steps = steps_before;
+            interpreter.scanned = scanned;
+            output.truncate(printed);
+
+            break;
+        }
+
+        start = end;
+    }
+
+    let rest = interpreter.instructions[start..]
+        .iter()
+        .map(|&instruction| match instruction {
+            Instruction::JumpIfZero(to) => Instruction::JumpIfZero(to - start),
+            Instruction::JumpIfNotZero(to) => Instruction::JumpIfNotZero(to - start),
+            instruction => instruction,
+        })
+        .collect();
+
+    // the compiled program only gets what the prefix didn't use up
+    let limits = Limits {
+        fuel: limits
+            .fuel
+            .map(|fuel| fuel.saturating_sub(interpreter.steps + interpreter.scanned)),
+        timeout: limits
+            .timeout
+            .map(|timeout| timeout.saturating_sub(started.elapsed())),
+        output: limits
+            .output
+            .map(|most| most.saturating_sub(output.len() as u64)),
+    };
+
+    Ok(Prefix {
+        output,
+        // nothing grew to the left, so cell 0 comes first
+        memory: interpreter
+            .memory
+            .cells()
+            .iter()
+            .map(|cell| cell.to_value())
+            .collect(),
+        pointer: interpreter.memptr as usize,
+        steps: interpreter.steps,
+        rest,
+        limits,
+    })
+}
+
+/// Cells the `MoveUntilZero(n)` under the instruction pointer moves over, if it stops within
+/// `budget` of them. Leaving the tape counts as stopping, running the scan then says what
+/// happens there.
+fn scan<C: Cell>(interpreter: &Interpreter<C>, n: isize, budget: usize) -> Option<usize> {
+    let tape = &interpreter.memory;
+    let mut at = interpreter.memptr;
+    let mut moves = 0;
+
+    while tape.positions().contains(&at) && tape[at] != C::default() {
+        if moves == budget {
+            return None;
+        }
+
+        at = tape.position(at + n);
+        moves += 1;
+    }
+
+    Some(moves)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use parser::{Instruction::*, OptimizationOptions, Parser};
+
+    fn evaluate(source: &[u8], budget: usize) -> Prefix {
+        evaluate_with(source, CellWidth::U8, budget)
+    }
+
+    fn evaluate_with(source: &[u8], width: CellWidth, budget: usize) -> Prefix {
+        let instructions = Parser::new(source)
+            .parse(OptimizationOptions::all())
+            .expect("failed to parse");
+
+        evaluate_prefix(
+            instructions,
+            width,
+            16,
+            TapePolicy::Wrap,
+            OutputMode::Bytes,
+            Limits::default(),
+            budget,
+        )
+        .expect("failed to evaluate")
+    }
+
+    fn evaluate_tape(source: &[u8], tape_size: usize, tape: TapePolicy) -> Prefix {
+        let instructions = Parser::new(source)
+            .parse(OptimizationOptions::all())
+            .expect("failed to parse");
+
+        evaluate_prefix(
+            instructions,
+            CellWidth::U8,
+            tape_size,
+            tape,
+            OutputMode::Bytes,
+            Limits::default(),
+            1000,
+        )
+        .expect("failed to evaluate")
+    }
+
+    #[test]
+    fn whole_program() {
+        let prefix = evaluate(b"++++++++[>++++++++<-]>+.+.>++", 1000);
+
+        assert_eq!(prefix.output, b"AB");
+        assert_eq!(&prefix.memory[..3], &[0, 66, 2]);
+        assert_eq!(prefix.pointer, 2);
+        assert_eq!(prefix.steps, 9);
+        assert!(prefix.rest.is_empty());
+    }
+
+    #[test]
+    fn stops_at_input() {
+        let prefix = evaluate(b"+++.>,.", 1000);
+
+        assert_eq!(prefix.output, b"\x03");
+        assert_eq!(prefix.pointer, 0);
+        assert_eq!(prefix.rest, &[In(1), Out(1), Move(1)]);
+    }
+
+    #[test]
+    fn loop_with_input_is_left_whole() {
+        let prefix = evaluate(b"+.[>+.,<]", 1000);
+
+        assert_eq!(prefix.output, b"\x01");
+        assert_eq!(prefix.memory[..2], [1, 0]);
+        assert_eq!(
+            prefix.rest,
+            &[JumpIfZero(4), Add(1, 1), Out(1), In(1), JumpIfNotZero(0)]
+        );
+    }
+
+    #[test]
+    fn out_of_budget() {
+        let prefix = evaluate(b"+.+[.]", 20);
+
+        assert_eq!(prefix.output, b"\x01");
+        assert_eq!(prefix.memory[0], 2);
+        // the loop that ran out of budget doesn't count
+        assert_eq!(prefix.steps, 3);
+        assert_eq!(prefix.rest, &[JumpIfZero(2), Out(0), JumpIfNotZero(0)]);
+
+        assert_eq!(evaluate(b"+.", 0).rest, &[Add(0, 1), Out(0)]);
+    }
+
+    #[test]
+    fn cell_width() {
+        let source = b"->++++++++++++++++[>++++++++++++++++<-]";
+
+        let prefix = evaluate_with(source, CellWidth::U8, 1000);
+        assert_eq!(prefix.memory[..3], [255, 0, 0]);
+
+        let prefix = evaluate_with(source, CellWidth::U16, 1000);
+        assert_eq!(prefix.memory[..3], [65535, 0, 256]);
+
+        let prefix = evaluate_with(source, CellWidth::U32, 1000);
+        assert_eq!(prefix.memory[..3], [u32::MAX, 0, 256]);
+    }
+
+    #[test]
+    fn tape_policy() {
+        let prefix = evaluate_tape(b"+<+.>>>>+", 4, TapePolicy::Wrap);
+        assert_eq!(prefix.memory, [1, 0, 0, 2]);
+        assert_eq!(prefix.pointer, 3);
+        assert!(prefix.rest.is_empty());
+
+        let prefix = evaluate_tape(b"+.<+.", 4, TapePolicy::Error);
+        assert_eq!(prefix.output, b"\x01");
+        assert_eq!(prefix.memory, [1, 0, 0, 0]);
+        assert_eq!(prefix.rest, &[Add(-1, 1), Out(-1), Move(-1)]);
+
+        let prefix = evaluate_tape(b">>>>>+", 4, TapePolicy::Grow);
+        assert_eq!(prefix.memory, [0, 0, 0, 0, 0, 1, 0, 0]);
+        assert_eq!(prefix.pointer, 5);
+    }
+
+    #[test]
+    fn scan() {
+        let prefix = evaluate_tape(b"+>+>+>[>]+", 4, TapePolicy::Wrap);
+        assert_eq!(prefix.memory, [1, 1, 1, 1]);
+        assert_eq!(prefix.pointer, 3);
+        // 4 adds, then a scan over 4 cells that takes 5 steps of budget
+        assert_eq!(evaluate(b"+>+>+>+<<<[>]", 9).rest, &[]);
+        assert_eq!(evaluate(b"+>+>+>+<<<[>]", 8).rest, &[MoveUntilZero(1)]);
+
+        // there's no zero cell to stop at
+        let prefix = evaluate_tape(b"+>+>+>+[>]", 4, TapePolicy::Wrap);
+        assert_eq!(prefix.memory, [1, 1, 1, 1]);
+        assert_eq!(prefix.pointer, 3);
+        assert_eq!(prefix.rest, &[MoveUntilZero(1)]);
+
+        let prefix = evaluate_tape(b"+>+>+>+[>]", 4, TapePolicy::Grow);
+        assert_eq!(prefix.pointer, 4);
+        assert!(prefix.rest.is_empty());
+    }
+
+    #[test]
+    fn limits_left() {
+        let instructions = Parser::new(b"+.>+<[>],")
+            .parse(OptimizationOptions::all())
+            .expect("failed to parse");
+        let limits = Limits {
+            fuel: Some(100),
+            timeout: Some(Duration::from_secs(60)),
+            output: Some(10),
+        };
+        let prefix = evaluate_prefix(
+            instructions,
+            CellWidth::U8,
+            16,
+            TapePolicy::Wrap,
+            OutputMode::Bytes,
+            limits,
+            1000,
+        )
+        .expect("failed to evaluate");
+
+        assert_eq!(prefix.rest, &[In(0)]);
+        // the scan moved over 2 cells
+        assert_eq!(prefix.limits.fuel, Some(100 - prefix.steps - 2));
+        assert!(prefix.limits.timeout < limits.timeout);
+        assert_eq!(prefix.limits.output, Some(9));
+    }
+}
@@ -0,0 +1,200 @@
+use std::{fmt::Write as _, ops::Range};
+
+use parser::{Instruction, Span};
+
+use crate::Profile;
+
+/// A loop, or the instruction one was folded into, with the loops nested in it.
+#[derive(Debug, Clone)]
+struct Frame {
+    label: String,
+    /// Times the program got to the loop and times its body ran, `None` for a folded one and
+    /// for the whole program.
+    counts: Option<(u64, u64)>,
+    /// Steps run in the loop itself, not in the ones nested in it.
+    own: u64,
+    /// Steps run in the loop, nested ones included.
+    total: u64,
+    children: Vec<Frame>,
+}
+
+impl Profile {
+    /// Every loop that ran as a stack of the loops it is nested in, one per line in the folded
+    /// format flame graph tools take, weighed by the steps run in it.
+    pub fn to_folded(&self, instructions: &[Instruction], spans: &[Span]) -> String {
+        let mut out = String::new();
+        let root = self.root(instructions, spans);
+
+        root.fold(&mut Vec::new(), &mut out);
+
+        out
+    }
+
+    /// Loops as Chrome trace events. There is one event per loop, not per time it ran, so it
+    /// looks like a flame graph: `ts` and `dur` count steps, not time, and the loops nested in
+    /// one are laid out next to each other, in program order.
+    pub fn to_chrome_trace(&self, instructions: &[Instruction], spans: &[Span]) -> String {
+        let mut out = String::from(r#"{"traceEvents":["#);
+        let root = self.root(instructions, spans);
+
+        root.trace(0, &mut out);
+        out.push_str("]}");
+
+        out
+    }
+
+    /// The whole program as a single frame.
+    fn root(&self, instructions: &[Instruction], spans: &[Span]) -> Frame {
+        let (own, children) = self.frames(instructions, spans, 0..instructions.len());
+
+        Frame {
+            label: "program".to_string(),
+            counts: None,
+            own,
+            total: self.steps(),
+            children,
+        }
+    }
+
+    /// Steps run by the instructions in `range` outside of any loop, and the loops in it that
+    /// ran.
+    fn frames(
+        &self,
+        instructions: &[Instruction],
+        spans: &[Span],
+        range: Range<usize>,
+    ) -> (u64, Vec<Frame>) {
+        use Instruction::*;
+
+        let mut own = 0;
+        let mut frames = Vec::new();
+        let mut idx = range.start;
+
+        while idx < range.end {
+            let executions = self.executions()[idx];
+
+            match instructions[idx] {
+                JumpIfZero(end) => {
+                    let (inner, children) = self.frames(instructions, spans, idx + 1..end);
+
+                    frames.push(Frame {
+                        label: format!("loop at {}", spans[idx]),
+                        counts: Some((executions, self.executions()[end])),
+                        own: executions + self.executions()[end] + inner,
+                        total: self.executions()[idx..=end].iter().sum(),
+                        children,
+                    });
+                    idx = end;
+                }
+                instruction @ (Multiply(..) | Clear(_) | Set(..) | MoveUntilZero(_)) => frames
+                    .push(Frame {
+                        label: format!("{instruction:?} at {}", spans[idx]),
+                        counts: None,
+                        own: executions,
+                        total: executions,
+                        children: Vec::new(),
+                    }),
+                _ => own += executions,
+            }
+
+            idx += 1;
+        }
+        frames.retain(|frame| frame.total != 0);
+
+        (own, frames)
+    }
+}
+
+impl Frame {
+    /// Writes a line for this frame and every frame nested in it, `stack` being the frames it
+    /// is nested in.
+    fn fold<'a>(&'a self, stack: &mut Vec<&'a str>, out: &mut String) {
+        stack.push(&self.label);
+
+        // writing to a `String` never fails
+        if self.own != 0 {
+            writeln!(out, "{} {}", stack.join(";"), self.own).unwrap();
+        }
+        for child in &self.children {
+            child.fold(stack, out);
+        }
+
+        stack.pop();
+    }
+
+    /// Writes an event for this frame starting at `ts`, and for every frame nested in it.
+    fn trace(&self, ts: u64, out: &mut String) {
+        if !out.ends_with('[') {
+            out.push(',');
+        }
+
+        write!(
+            out,
+            r#"{{"name":"{}","ph":"X","ts":{ts},"dur":{},"pid":1,"tid":1"#,
+            self.label, self.total
+        )
+        .unwrap(); // writing to a `String` never fails
+        if let Some((entries, iterations)) = self.counts {
+            write!(
+                out,
+                r#","args":{{"entries":{entries},"iterations":{iterations}}}"#
+            )
+            .unwrap();
+        }
+        out.push('}');
+
+        let mut ts = ts;
+        for child in &self.children {
+            child.trace(ts, out);
+            ts += child.total;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::profile::tests::profile;
+    use parser::OptimizationOptions;
+
+    #[test]
+    fn flame_graph() {
+        let (instructions, spans, profile) = profile(
+            b"++[>+++[>+<-]<-]\n>[-]",
+            OptimizationOptions::new().with_clear(),
+        );
+
+        assert_eq!(
+            profile.to_folded(&instructions, &spans),
+            "program 3
+program;loop at 1:3 15
+program;loop at 1:3;loop at 1:8 32
+program;Clear(0) at 2:2 1
+"
+        );
+        assert_eq!(
+            profile.to_chrome_trace(&instructions, &spans),
+            r#"{"traceEvents":[{"name":"program","ph":"X","ts":0,"dur":51,"pid":1,"tid":1},{"name":"loop at 1:3","ph":"X","ts":0,"dur":47,"pid":1,"tid":1,"args":{"entries":1,"iterations":2}},{"name":"loop at 1:8","ph":"X","ts":0,"dur":32,"pid":1,"tid":1,"args":{"entries":2,"iterations":6}},{"name":"Clear(0) at 2:2","ph":"X","ts":47,"dur":1,"pid":1,"tid":1}]}"#
+        );
+    }
+
+    #[test]
+    fn empty() {
+        let (instructions, spans, profile) = profile(b"just a comment", OptimizationOptions::all());
+
+        assert_eq!(profile.to_folded(&instructions, &spans), "");
+        assert_eq!(
+            profile.to_chrome_trace(&instructions, &spans),
+            r#"{"traceEvents":[{"name":"program","ph":"X","ts":0,"dur":0,"pid":1,"tid":1}]}"#
+        );
+    }
+
+    #[test]
+    fn starts_with_loop() {
+        let (instructions, spans, profile) = profile(b"[-]+", OptimizationOptions::new());
+
+        assert_eq!(
+            profile.to_chrome_trace(&instructions, &spans),
+            r#"{"traceEvents":[{"name":"program","ph":"X","ts":0,"dur":2,"pid":1,"tid":1},{"name":"loop at 1:1","ph":"X","ts":0,"dur":1,"pid":1,"tid":1,"args":{"entries":1,"iterations":0}}]}"#
+        );
+    }
+}
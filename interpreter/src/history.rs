@@ -0,0 +1,536 @@
+use std::collections::VecDeque;
+
+use color_eyre::{eyre::eyre, Result};
+
+use parser::Instruction;
+
+use crate::{Cell, Interpreter, StepResult, Tape};
+
+/// Most checkpoints kept, every other one goes once there are more.
+const MAX_CHECKPOINTS: usize = 64;
+
+/// What an interpreter remembers of a run so it can go backwards, see
+/// [`Interpreter::with_history`].
+///
+/// The last steps are in an undo log that is walked back one step at a time. Going back further
+/// restores the latest checkpoint before and runs forward from there, giving `,` what it read
+/// the first time around.
+#[derive(Debug, Clone)]
+pub(crate) struct History<C: Cell> {
+    /// How to take back each of the last steps, oldest first.
+    undo: VecDeque<Undo<C>>,
+    capacity: usize,
+    /// Oldest first, at least `interval` steps apart.
+    checkpoints: Vec<Checkpoint<C>>,
+    interval: u64,
+    /// Every byte `,` got, or `None` for the end of input, starting with `,` number
+    /// `inputs_start`.
+    inputs: Vec<Option<u8>>,
+    inputs_start: u64,
+    /// `,` and `.` run so far, undoing one takes it off.
+    reads: u64,
+    writes: u64,
+    /// Most `.` run at any point, the ones up to it were already written.
+    shown: u64,
+}
+
+/// How to take back a single step.
+#[derive(Debug, Clone, Copy)]
+struct Undo<C: Cell> {
+    instptr: usize,
+    memptr: isize,
+    /// Cell the step wrote, with the value it had before.
+    cell: Option<(isize, C)>,
+    /// Cells scans moved over before the step.
+    scanned: u64,
+}
+
+/// Everything a run can be picked up from.
+#[derive(Debug, Clone)]
+struct Checkpoint<C: Cell> {
+    tape: Tape<C>,
+    memptr: isize,
+    instptr: usize,
+    steps: u64,
+    scanned: u64,
+    written: u64,
+    reads: u64,
+    writes: u64,
+}
+
+impl<C: Cell> History<C> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            capacity,
+            checkpoints: Vec::new(),
+            interval: capacity.max(1) as u64,
+            inputs: Vec::new(),
+            inputs_start: 0,
+            reads: 0,
+            writes: 0,
+            shown: 0,
+        }
+    }
+
+    /// Drops everything before now, and what was read after it.
+    pub(crate) fn forget(&mut self) {
+        self.undo.clear();
+        self.checkpoints.clear();
+        self.inputs.clear();
+        self.inputs_start = self.reads;
+        self.shown = self.writes;
+    }
+
+    /// Index in `inputs` of what the next `,` reads.
+    fn next_input(&self) -> usize {
+        (self.reads - self.inputs_start) as usize
+    }
+}
+
+impl<C: Cell> Interpreter<C> {
+    /// Step count the history goes back to, `None` if it isn't recorded.
+    pub fn history_start(&self) -> Option<u64> {
+        let history = self.history.as_ref()?;
+
+        Some(
+            history
+                .checkpoints
+                .first()
+                .map_or(self.steps, |first| first.steps),
+        )
+    }
+
+    /// Runs the program backwards by up to `steps` steps, as far as the history goes, and
+    /// returns how many it went. Also takes back an error or limit the program stopped at.
+    pub fn rewind(&mut self, steps: u64) -> Result<u64> {
+        let start = self
+            .history_start()
+            .ok_or_else(|| eyre!("the interpreter doesn't record its history"))?;
+        let target = self.steps.saturating_sub(steps).max(start);
+        let rewound = self.steps - target;
+
+        self.resume();
+        self.seek(target)?;
+
+        Ok(rewound)
+    }
+
+    /// Runs the program backwards until `predicate` holds before one of the instructions that
+    /// ran. If it doesn't as far as the history goes, the program is left where it was and this
+    /// returns false.
+    pub fn rewind_until(&mut self, mut predicate: impl FnMut(&Self) -> bool) -> Result<bool> {
+        if self.history.is_none() {
+            return Err(eyre!("the interpreter doesn't record its history"));
+        }
+        let (steps, stop) = (self.steps, self.stop.clone());
+
+        self.resume();
+        while self.undo() {
+            if predicate(self) {
+                self.refresh_watchpoints();
+
+                return Ok(true);
+            }
+        }
+
+        // before the undo log, run forward from each checkpoint to where the later ones were
+        // already looked at
+        let mut end = self.steps;
+        for idx in (0..self.checkpoints().len()).rev() {
+            let start = self.checkpoints()[idx].steps;
+            if start >= end {
+                continue;
+            }
+
+            self.restore(idx);
+
+            let mut found = None;
+            while self.steps < end {
+                if predicate(self) {
+                    found = Some(self.steps);
+                }
+
+                self.replay(self.steps + 1)?;
+            }
+
+            if let Some(found) = found {
+                self.seek(found)?;
+
+                return Ok(true);
+            }
+
+            end = start;
+        }
+
+        self.seek(steps)?;
+        if let Some(stop) = stop {
+            self.stop(stop);
+        }
+
+        Ok(false)
+    }
+
+    /// Runs the program backwards to right before the last instruction that wrote the cell at
+    /// `position`. Returns false if none did as far as the history goes, leaving the program
+    /// where it was.
+    pub fn rewind_to_write(&mut self, position: isize) -> Result<bool> {
+        self.rewind_until(|interpreter| interpreter.target() == Some(position))
+    }
+
+    /// Position of the cell the next instruction writes, if it writes one.
+    pub(crate) fn target(&self) -> Option<isize> {
+        use Instruction::*;
+
+        let at = |offset| self.memory.position(self.memptr + offset);
+
+        match *self.instructions.get(self.instptr)? {
+            Add(offset, _) | Clear(offset) | Set(offset, _) | In(offset) => Some(at(offset)),
+            Multiply(from, to, _) => {
+                let from = at(from);
+
+                // only if the loop it came from would have run
+                (self.memory.positions().contains(&from) && self.memory[from] != C::default())
+                    .then(|| at(to))
+            }
+            _ => None,
+        }
+    }
+
+    /// Same as [`Interpreter::execute_next`], remembering how to take it back.
+    pub(crate) fn step_recorded(&mut self) -> Result<StepResult> {
+        let undo = self.undo_entry();
+        let steps = self.steps;
+
+        self.checkpoint();
+        let result = self.execute_next();
+        if self.steps > steps {
+            self.record(undo);
+        }
+
+        result
+    }
+
+    /// Runs a `,` reading `byte`, remembering how to take it back and what it read.
+    pub(crate) fn input_recorded(&mut self, offset: isize, byte: Option<u8>) -> Result<()> {
+        let undo = self.undo_entry();
+
+        self.checkpoint();
+        let result = self.read(offset, byte);
+        if result.is_ok() {
+            let history = self.history.as_mut().expect("the history is recorded");
+            let next = history.next_input();
+
+            // reading something else than last time leads somewhere else from here on
+            if history.inputs.get(next).is_some_and(|&read| read != byte) {
+                let steps = self.steps - 1;
+
+                history
+                    .checkpoints
+                    .retain(|checkpoint| checkpoint.steps <= steps);
+                history.inputs.truncate(next);
+                history.shown = history.writes;
+            }
+            if next == history.inputs.len() {
+                history.inputs.push(byte);
+            }
+
+            self.record(undo);
+        }
+
+        self.check(result)
+    }
+
+    /// What the `,` the program waits on read before it went back, if it got this far.
+    pub(crate) fn replayed_input(&self) -> Option<Option<u8>> {
+        let history = self.history.as_ref()?;
+
+        history.inputs.get(history.next_input()).copied()
+    }
+
+    /// Whether the `.` that just ran has to be written, it doesn't if it was before the program
+    /// went back.
+    pub(crate) fn is_new_output(&mut self) -> bool {
+        match self.history {
+            Some(ref mut history) if history.writes <= history.shown => false,
+            Some(ref mut history) => {
+                history.shown = history.writes;
+
+                true
+            }
+            None => true,
+        }
+    }
+
+    fn checkpoints(&self) -> &[Checkpoint<C>] {
+        self.history
+            .as_ref()
+            .map_or(&[], |history| &history.checkpoints)
+    }
+
+    /// Lets the program go on after an error or a limit.
+    fn resume(&mut self) {
+        self.stop = None;
+        self.next_check = 0;
+    }
+
+    fn undo_entry(&self) -> Undo<C> {
+        let cell = self.target().map(|at| {
+            // a cell the tape doesn't have yet is zero once it grows to it
+            let value = match self.memory.positions().contains(&at) {
+                true => self.memory[at],
+                false => C::default(),
+            };
+
+            (at, value)
+        });
+
+        Undo {
+            instptr: self.instptr,
+            memptr: self.memptr,
+            cell,
+            scanned: self.scanned,
+        }
+    }
+
+    /// Adds how to take back the step that just ran to the undo log.
+    fn record(&mut self, undo: Undo<C>) {
+        let history = self.history.as_mut().expect("the history is recorded");
+
+        match self.instructions[undo.instptr] {
+            Instruction::In(_) => history.reads += 1,
+            Instruction::Out(_) => history.writes += 1,
+            _ => {}
+        }
+
+        history.undo.push_back(undo);
+        if history.undo.len() > history.capacity {
+            history.undo.pop_front();
+        }
+    }
+
+    /// Takes back the last step in the undo log, returns false if it is empty.
+    fn undo(&mut self) -> bool {
+        let Some(ref mut history) = self.history else {
+            return false;
+        };
+        let Some(undo) = history.undo.pop_back() else {
+            return false;
+        };
+
+        self.instptr = undo.instptr;
+        self.memptr = undo.memptr;
+        self.steps -= 1;
+        self.scanned = undo.scanned;
+        if let Some((at, value)) = undo.cell {
+            self.memory[at] = value;
+        }
+
+        match self.instructions[self.instptr] {
+            Instruction::In(_) => history.reads -= 1,
+            Instruction::Out(offset) => {
+                history.writes -= 1;
+
+                if self.limits.output.is_some() {
+                    let value = self.memory[self.memory.position(self.memptr + offset)].to_value();
+
+                    self.written -= self.output.encode(value, &mut [0; 4]).len() as u64;
+                }
+            }
+            _ => {}
+        }
+
+        true
+    }
+
+    /// Takes a checkpoint if the last one is far enough back.
+    fn checkpoint(&mut self) {
+        let Some(ref mut history) = self.history else {
+            return;
+        };
+        if history
+            .checkpoints
+            .last()
+            .is_some_and(|last| self.steps < last.steps + history.interval)
+        {
+            return;
+        }
+
+        history.checkpoints.push(Checkpoint {
+            tape: self.memory.clone(),
+            memptr: self.memptr,
+            instptr: self.instptr,
+            steps: self.steps,
+            scanned: self.scanned,
+            written: self.written,
+            reads: history.reads,
+            writes: history.writes,
+        });
+
+        // keeping every other one, the newest included, keeps the whole run reachable
+        if history.checkpoints.len() > MAX_CHECKPOINTS {
+            let mut keep = false;
+            history.checkpoints.retain(|_| {
+                keep = !keep;
+                keep
+            });
+            history.interval *= 2;
+        }
+    }
+
+    /// Picks the run up from the checkpoint at `idx`.
+    fn restore(&mut self, idx: usize) {
+        let history = self.history.as_mut().expect("the history is recorded");
+        let checkpoint = history.checkpoints[idx].clone();
+
+        history.undo.clear();
+        history.reads = checkpoint.reads;
+        history.writes = checkpoint.writes;
+
+        self.memory = checkpoint.tape;
+        self.memptr = checkpoint.memptr;
+        self.instptr = checkpoint.instptr;
+        self.steps = checkpoint.steps;
+        self.scanned = checkpoint.scanned;
+        self.written = checkpoint.written;
+    }
+
+    /// Gets to where the program was after `target` steps, which the history has to reach.
+    fn seek(&mut self, target: u64) -> Result<()> {
+        let undone = self
+            .history
+            .as_ref()
+            .map_or(0, |history| history.undo.len());
+        let start = self.steps - undone as u64;
+
+        let checkpoints = self.checkpoints();
+        if let Some(idx) = checkpoints
+            .iter()
+            .rposition(|checkpoint| checkpoint.steps <= target)
+        {
+            // a checkpoint past where the program is now saves running up to it
+            if target < start || checkpoints[idx].steps > self.steps {
+                self.restore(idx);
+            }
+        }
+
+        while self.steps > target && self.undo() {}
+
+        let result = self.replay(target);
+        self.refresh_watchpoints();
+
+        result
+    }
+
+    /// Runs forward to `target` steps, which the program got to before, giving `,` what it read
+    /// then and writing nothing.
+    fn replay(&mut self, target: u64) -> Result<()> {
+        // getting back to where it was can't take too long
+        let deadline = self.deadline.take();
+
+        let mut result = Ok(());
+        while result.is_ok() && self.steps < target {
+            result = match self.step() {
+                Ok(StepResult::NeedsInput) => match self.replayed_input() {
+                    Some(byte) => self.input(byte),
+                    None => Err(eyre!("the history has no input left to replay")),
+                },
+                Ok(StepResult::Continued | StepResult::Output(_)) => Ok(()),
+                Ok(StepResult::Halted | StepResult::LimitExceeded(_)) => Err(eyre!(
+                    "the program stopped before step {target} when replaying it"
+                )),
+                Err(err) => Err(err),
+            };
+        }
+
+        self.deadline = deadline;
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Io, MachineState};
+    use parser::{Parser, TapePolicy};
+
+    /// States after every step of `source` run on `input`, unoptimized so there are many.
+    fn run(source: &[u8], input: &[u8], capacity: usize) -> (Interpreter<u8>, Vec<MachineState>) {
+        let instructions = Parser::new(source)
+            .parse_raw()
+            .expect("failed to parse")
+            .into_iter()
+            .map(|instruction| instruction.value)
+            .collect();
+
+        let mut interpreter = Interpreter::new(instructions)
+            .with_tape(Tape::new(4, TapePolicy::Wrap))
+            .with_history(capacity);
+        let mut io = Io::new(input, Vec::new());
+        let mut states = vec![interpreter.state()];
+        interpreter
+            .run_until(&mut io, |interpreter| {
+                states.push(interpreter.state());
+                false
+            })
+            .expect("failed to run");
+
+        (interpreter, states)
+    }
+
+    #[test]
+    fn rewind() {
+        const SOURCE: &[u8] = b",[>++++[>+++<-]<-]>>.";
+
+        for capacity in [0, 3, 1000] {
+            let (mut interpreter, states) = run(SOURCE, &[20], capacity);
+            assert_eq!(interpreter.history_start(), Some(0));
+
+            for state in states.iter().rev().skip(1) {
+                assert_eq!(interpreter.rewind(1).unwrap(), 1);
+                assert_eq!(&interpreter.state(), state);
+            }
+            assert_eq!(interpreter.rewind(1).unwrap(), 0);
+        }
+    }
+
+    #[test]
+    fn replay() {
+        let (mut interpreter, states) = run(b",.,.", b"ab", 4);
+        assert_eq!(interpreter.rewind(3).unwrap(), 3);
+
+        // the input was all read, so it has to come from the history
+        let mut io = Io::new(&b""[..], Vec::new());
+        interpreter.run_until(&mut io, |_| false).unwrap();
+
+        assert_eq!(io.into_output().unwrap(), b"");
+        assert_eq!(&interpreter.state(), states.last().unwrap());
+    }
+
+    #[test]
+    fn checkpoints() {
+        let (mut interpreter, states) = run(b"++++++++[>++++++++[>++++<-]<-]", b"", 1);
+        let history = interpreter.history.as_ref().unwrap();
+        assert!(history.checkpoints.len() <= MAX_CHECKPOINTS);
+        assert!(history.interval > 1);
+
+        let steps = states.len() as u64 - 1;
+        assert_eq!(interpreter.rewind(steps / 2).unwrap(), steps / 2);
+        assert_eq!(interpreter.state(), states[(steps - steps / 2) as usize]);
+        assert_eq!(interpreter.rewind(u64::MAX).unwrap(), steps - steps / 2);
+        assert_eq!(interpreter.state(), states[0]);
+    }
+
+    #[test]
+    fn last_write() {
+        let (mut interpreter, _) = run(b"+>++<+>+", b"", 2);
+
+        assert!(interpreter.rewind_to_write(0).unwrap());
+        assert_eq!(interpreter.instptr(), 5);
+        assert_eq!(interpreter.tape().cells()[..2], [1, 2]);
+
+        assert!(!interpreter.rewind_to_write(2).unwrap());
+        assert_eq!(interpreter.instptr(), 5);
+    }
+}
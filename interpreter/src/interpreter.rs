@@ -1,81 +1,560 @@
-use color_eyre::{eyre::Context, Result};
+use std::{
+    io::{Read, Write},
+    time::Instant,
+};
 
-use parser::Instruction;
+use color_eyre::{
+    eyre::{eyre, Context},
+    Report, Result,
+};
 
-#[derive(Debug, Clone)]
-pub struct Interpreter<const N: usize> {
-    memory: [u8; N],
-    memptr: usize,
-    instructions: Vec<Instruction>,
-    instptr: usize,
+use parser::{EofPolicy, Instruction, OutputMode};
+
+use crate::{
+    history::History, trace::TraceRecord, watch::Watch, Cell, ExitReason, Io, Limit, Limits,
+    MachineState, Profile, Tape,
+};
+
+/// What running a single instruction did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StepResult {
+    /// Ran an instruction that didn't read or write anything.
+    Continued,
+    /// Got to a `,` and waits for [`Interpreter::input`] to run it.
+    NeedsInput,
+    /// Ran a `.` on a cell holding this value, the output mode says how to write it.
+    Output(u32),
+    /// There's nothing left to run.
+    Halted,
+    /// Stopped at a limit before running the instruction under the pointer.
+    LimitExceeded(Limit),
 }
 
-impl<const N: usize> Interpreter<N> {
-    const LENGTH: isize = N as isize;
+/// Steps between looking at the clock when there's a timeout.
+const CLOCK_INTERVAL: u64 = 1 << 16;
 
-    pub const fn new(instructions: Vec<Instruction>) -> Self {
+/// Runs parsed instructions one at a time, on its own tape.
+#[derive(Debug, Clone)]
+pub struct Interpreter<C: Cell> {
+    pub(crate) memory: Tape<C>,
+    pub(crate) memptr: isize,
+    pub(crate) instructions: Vec<Instruction>,
+    pub(crate) instptr: usize,
+    pub(crate) steps: u64,
+    /// Cells every `MoveUntilZero` moved over so far, they use up fuel as well as steps.
+    pub(crate) scanned: u64,
+    /// Set once the program stopped before the end, with an error or at a limit.
+    pub(crate) stop: Option<ExitReason>,
+    eof: EofPolicy,
+    pub(crate) output: OutputMode,
+    pub(crate) limits: Limits,
+    pub(crate) deadline: Option<Instant>,
+    /// Step count at which the limits have to be looked at again, 0 once the program stopped,
+    /// while it records its history, while it is profiled or traced or while there are
+    /// watchpoints.
+    pub(crate) next_check: u64,
+    /// Bytes written so far, only counted with an output limit.
+    pub(crate) written: u64,
+    pub(crate) history: Option<History<C>>,
+    pub(crate) watchpoints: Vec<Watch>,
+    /// ID the next watchpoint gets, never one a deleted watchpoint had.
+    pub(crate) next_watch_id: usize,
+    /// Watchpoint that held after the last step.
+    pub(crate) hit: Option<usize>,
+    pub(crate) profile: Option<Profile>,
+    pub(crate) tracing: bool,
+    /// What the last step did, while the program is traced.
+    pub(crate) traced: Option<TraceRecord>,
+}
+
+impl<C: Cell> Interpreter<C> {
+    pub fn new(instructions: Vec<Instruction>) -> Self {
         Self {
-            memory: [0u8; N],
+            memory: Tape::default(),
             memptr: 0,
             instructions,
             instptr: 0,
+            steps: 0,
+            scanned: 0,
+            stop: None,
+            eof: EofPolicy::default(),
+            output: OutputMode::default(),
+            limits: Limits::default(),
+            deadline: None,
+            next_check: u64::MAX,
+            written: 0,
+            history: None,
+            watchpoints: Vec::new(),
+            next_watch_id: 1,
+            hit: None,
+            profile: None,
+            tracing: false,
+            traced: None,
         }
     }
 
-    pub fn run(mut self) -> Result<()> {
-        let mut stdout = std::io::stdout().lock();
-        let mut stdin = std::io::stdin().lock();
+    pub fn with_tape(mut self, tape: Tape<C>) -> Self {
+        self.memory = tape;
 
-        while let Some(instruction) = self.instructions.get(self.instptr) {
-            use Instruction::*;
+        self
+    }
 
-            match instruction {
-                Add(n) => self.memory[self.memptr] = self.memory[self.memptr].wrapping_add(*n),
-                Move(n) => {
-                    let n = (Self::LENGTH + n % Self::LENGTH) as usize;
+    pub fn with_eof(mut self, eof: EofPolicy) -> Self {
+        self.eof = eof;
 
-                    self.memptr = (self.memptr + n) % Self::LENGTH as usize;
-                }
-                In => {
-                    use std::io::Read;
+        self
+    }
 
-                    stdin
-                        .read_exact(&mut self.memory[self.memptr..self.memptr + 1])
-                        .wrap_err("failed to read from stdin")?;
-                }
-                Out => {
-                    use std::io::Write;
+    pub fn with_output(mut self, output: OutputMode) -> Self {
+        self.output = output;
 
-                    write!(stdout, "{}", self.memory[self.memptr] as char)
-                        .wrap_err("failed to write to stdout")?;
-                }
-                JumpIfZero(to) if self.memory[self.memptr] == 0 => {
-                    self.instptr = *to;
+        self
+    }
+
+    /// Stops the program once it reaches one of `limits`, the timeout counts from here.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self.deadline = limits
+            .timeout
+            .and_then(|timeout| Instant::now().checked_add(timeout));
+        self.next_check = 0;
+
+        self
+    }
+
+    /// Records the last `capacity` steps in an undo log, and a checkpoint every so often, so the
+    /// program can be run backwards with [`Interpreter::rewind`]. Checkpoints thin out the
+    /// longer the program runs, which keeps the history to a bounded amount of memory.
+    pub fn with_history(mut self, capacity: usize) -> Self {
+        self.history = Some(History::new(capacity));
+        self.next_check = 0;
+
+        self
+    }
+
+    /// Counts how many times each instruction runs from here on, see [`Interpreter::profile`].
+    /// Steps taken again after a rewind count again.
+    pub fn with_profile(mut self) -> Self {
+        self.profile = Some(Profile::new(self.instructions.len()));
+        self.next_check = 0;
+
+        self
+    }
+
+    /// Runs the program on standard input and output.
+    pub fn run(mut self) -> Result<MachineState> {
+        self.run_with(std::io::stdin(), std::io::stdout())
+    }
+
+    /// Runs the rest of the program, reading `,` from `input` and writing `.` to `output`.
+    /// Both get buffered, `output` is flushed before this returns, even on an error. What the
+    /// program left is in [`Interpreter::state`] either way. Reaching a limit isn't an error,
+    /// the state says which one it was.
+    pub fn run_with(&mut self, input: impl Read, output: impl Write) -> Result<MachineState> {
+        let mut io = Io::new(input, output);
+
+        self.run_until(&mut io, |_| false)?;
+
+        let result = io.flush().wrap_err("failed to write output");
+        self.check(result)?;
+
+        Ok(self.state())
+    }
+
+    /// Runs instructions until `predicate` holds after one of them or the program ends, doing
+    /// `,` and `.` with `io`. Returns whether `predicate` was what stopped it, it wasn't if the
+    /// program reached a limit or a watchpoint. After [`Interpreter::rewind`], `,` gets what it
+    /// read before and `.` doesn't write again until the program gets past where it was.
+    pub fn run_until<R: Read, W: Write>(
+        &mut self,
+        io: &mut Io<R, W>,
+        mut predicate: impl FnMut(&Self) -> bool,
+    ) -> Result<bool> {
+        loop {
+            match self.step()? {
+                StepResult::Continued => {}
+                StepResult::NeedsInput => {
+                    let byte = match self.replayed_input() {
+                        Some(byte) => byte,
+                        None => {
+                            let result = io.read().wrap_err("failed to read input");
+                            self.check(result)?
+                        }
+                    };
+
+                    self.input(byte)?;
                 }
-                JumpIfNotZero(to) if self.memory[self.memptr] != 0 => {
-                    self.instptr = *to;
+                StepResult::Output(_) if !self.is_new_output() => {}
+                StepResult::Output(value) => {
+                    let mut buffer = [0; 4];
+                    let result = io
+                        .write(self.output.encode(value, &mut buffer))
+                        .wrap_err("failed to write output");
+
+                    self.check(result)?;
                 }
-                Clear => self.memory[self.memptr] = 0,
-                Multiply(offset, by) => {
-                    let n = (Self::LENGTH + offset % Self::LENGTH) as usize;
-                    let to = (self.memptr + n) % Self::LENGTH as usize;
-
-                    let imm = self.memory[self.memptr].wrapping_mul(*by);
-                    self.memory[to] = self.memory[to].wrapping_add(imm);
-                    self.memory[self.memptr] = 0;
+                StepResult::Halted | StepResult::LimitExceeded(_) => return Ok(false),
+            }
+
+            if self.hit.is_some() {
+                return Ok(false);
+            }
+            if predicate(self) {
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Whether the program stopped, either at its end, with an error or at a limit.
+    pub fn is_done(&self) -> bool {
+        self.stop.is_some() || self.instptr >= self.instructions.len()
+    }
+
+    /// Runs the instruction under the instruction pointer, unless it is `,`, which waits for
+    /// [`Interpreter::input`]. Once the program stopped with an error or at a limit, gives that
+    /// again.
+    // with more than one caller, the debugger's included, it stops getting inlined on its own
+    #[inline(always)]
+    pub fn step(&mut self) -> Result<StepResult> {
+        // a single compare keeps the common case fast
+        if self.steps >= self.next_check {
+            return self.step_checked();
+        }
+
+        self.execute_next()
+    }
+
+    /// Same as [`Interpreter::step`], looking at everything that isn't looked at on every step.
+    #[cold]
+    #[inline(never)]
+    fn step_checked(&mut self) -> Result<StepResult> {
+        if let Some(result) = self.stopped() {
+            return result;
+        }
+
+        let (instptr, steps) = (self.instptr, self.steps);
+        let record = self.trace_before();
+        let result = if !self.watchpoints.is_empty() {
+            self.step_watched()
+        } else if self.history.is_some() {
+            self.step_recorded()
+        } else {
+            self.execute_next()
+        };
+        if self.steps > steps {
+            self.count(instptr);
+            self.trace_after(record);
+        }
+
+        result
+    }
+
+    /// Runs the next instruction, without looking at the limits or recording it.
+    #[inline(always)]
+    pub(crate) fn execute_next(&mut self) -> Result<StepResult> {
+        let Some(&instruction) = self.instructions.get(self.instptr) else {
+            return Ok(StepResult::Halted);
+        };
+
+        match instruction {
+            Instruction::In(_) => Ok(StepResult::NeedsInput),
+            Instruction::Out(offset) => match self.write(offset) {
+                Ok(value) => Ok(StepResult::Output(value)),
+                Err(err) => self.fail(err),
+            },
+            instruction => match self.execute(instruction) {
+                Ok(()) => Ok(StepResult::Continued),
+                Err(err) => self.fail(err),
+            },
+        }
+    }
+
+    /// Runs the `,` the program is waiting on with `byte`, `None` meaning the input is over.
+    pub fn input(&mut self, byte: Option<u8>) -> Result<()> {
+        let Some(&Instruction::In(offset)) = self.instructions.get(self.instptr) else {
+            return Err(eyre!("the program isn't waiting for input"));
+        };
+        let instptr = self.instptr;
+        let record = self.trace_before();
+        let result = if !self.watchpoints.is_empty() {
+            self.input_watched(offset, byte)
+        } else if self.history.is_some() {
+            self.input_recorded(offset, byte)
+        } else {
+            let result = self.read(offset, byte);
+            self.check(result)
+        };
+        if result.is_ok() {
+            self.count(instptr);
+            self.trace_after(record);
+        }
+
+        result
+    }
+
+    /// Tape, pointer and step count as they are now.
+    pub fn state(&self) -> MachineState {
+        let exit = match self.stop {
+            Some(ref reason) => Some(reason.clone()),
+            None if self.is_done() => Some(ExitReason::Halted),
+            None => None,
+        };
+
+        MachineState {
+            tape: self
+                .memory
+                .cells()
+                .iter()
+                .map(|cell| cell.to_value())
+                .collect(),
+            start: self.memory.positions().start,
+            memptr: self.memptr,
+            steps: self.steps,
+            exit,
+        }
+    }
+
+    /// Remembers the error if `result` is one, so the program stays stopped.
+    pub(crate) fn check<T>(&mut self, result: Result<T>) -> Result<T> {
+        if let Err(ref err) = result {
+            self.stop(ExitReason::Error(format!("{err:#}")));
+        }
+
+        result
+    }
+
+    pub(crate) fn stop(&mut self, reason: ExitReason) {
+        self.stop = Some(reason);
+        self.next_check = 0;
+    }
+
+    /// What [`Interpreter::step`] gives if the program stopped or is about to reach a limit.
+    fn stopped(&mut self) -> Option<Result<StepResult>> {
+        match self.stop {
+            Some(ExitReason::Error(ref message)) => return Some(Err(eyre!(message.clone()))),
+            Some(ExitReason::LimitExceeded(limit)) => {
+                return Some(Ok(StepResult::LimitExceeded(limit)))
+            }
+            _ => {}
+        }
+
+        // getting to the end is fine, however many steps it took
+        if self.instptr >= self.instructions.len() {
+            return None;
+        }
+
+        let limit = self.exceeded()?;
+        self.stop(ExitReason::LimitExceeded(limit));
+
+        Some(Ok(StepResult::LimitExceeded(limit)))
+    }
+
+    /// Stops the program with `err`, unless it is a [`Limit`], which isn't an error.
+    #[cold]
+    fn fail(&mut self, err: Report) -> Result<StepResult> {
+        match err.downcast::<Limit>() {
+            Ok(limit) => {
+                self.stop(ExitReason::LimitExceeded(limit));
+
+                Ok(StepResult::LimitExceeded(limit))
+            }
+            Err(err) => self.check(Err(err)),
+        }
+    }
+
+    /// The limit the program reached, if any, otherwise works out when to look again.
+    fn exceeded(&mut self) -> Option<Limit> {
+        if self
+            .limits
+            .fuel
+            .is_some_and(|fuel| self.steps + self.scanned >= fuel)
+        {
+            return Some(Limit::Fuel);
+        }
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Some(Limit::Timeout);
+        }
+
+        let clock = match self.deadline {
+            Some(_) => self.steps + CLOCK_INTERVAL,
+            None => u64::MAX,
+        };
+        // every step gets recorded, counted, traced or looked at by the watchpoints
+        self.next_check = match self.history.is_some()
+            || self.profile.is_some()
+            || self.tracing
+            || !self.watchpoints.is_empty()
+        {
+            true => 0,
+            false => self
+                .limits
+                .fuel
+                .map_or(u64::MAX, |fuel| fuel - self.scanned)
+                .min(clock),
+        };
+
+        None
+    }
+
+    pub(crate) fn read(&mut self, offset: isize, byte: Option<u8>) -> Result<()> {
+        let at = self.index(offset)?;
+
+        match byte {
+            Some(byte) => self.memory[at] = C::from_byte(byte),
+            None => match self.eof {
+                EofPolicy::Unchanged => {}
+                EofPolicy::Zero => self.memory[at] = C::default(),
+                EofPolicy::MinusOne => self.memory[at] = C::from_value(-1),
+                EofPolicy::Error => return Err(eyre!("unexpected end of input")),
+            },
+        }
+
+        self.advance();
+
+        Ok(())
+    }
+
+    /// Value of the cell `.` writes.
+    fn write(&mut self, offset: isize) -> Result<u32> {
+        let at = self.index(offset)?;
+        let value = self.memory[at].to_value();
+
+        if let Some(max) = self.limits.output {
+            let written = self.written + self.output.encode(value, &mut [0; 4]).len() as u64;
+            if written > max {
+                return Err(Limit::Output.into());
+            }
+
+            self.written = written;
+        }
+
+        self.advance();
+
+        Ok(value)
+    }
+
+    #[inline(always)]
+    fn execute(&mut self, instruction: Instruction) -> Result<()> {
+        use Instruction::*;
+
+        match instruction {
+            Add(offset, n) => {
+                let at = self.index(offset)?;
+
+                self.memory[at] = self.memory[at].add(n);
+            }
+            Move(n) => self.memptr = self.index(n)?,
+            JumpIfZero(to) if self.memory[self.memptr] == C::default() => {
+                self.instptr = to;
+            }
+            JumpIfNotZero(to) if self.memory[self.memptr] != C::default() => {
+                self.instptr = to;
+            }
+            Clear(offset) => {
+                let at = self.index(offset)?;
+
+                self.memory[at] = C::default();
+            }
+            Set(offset, n) => {
+                let at = self.index(offset)?;
+
+                self.memory[at] = C::from_value(n);
+            }
+            Multiply(from, to, by) => {
+                let from = self.index(from)?;
+
+                // the loop this came from never ran, so it didn't get to the target either
+                if self.memory[from] != C::default() {
+                    let to = self.index(to)?;
+
+                    self.memory[to] = self.memory[to].mul_add(self.memory[from], by);
                 }
-                MoveUntilZero(n) => {
-                    let n = (Self::LENGTH + n % Self::LENGTH) as usize;
-                    while self.memory[self.memptr] != 0 {
-                        self.memptr = (self.memptr + n) % Self::LENGTH as usize;
+            }
+            MoveUntilZero(n) => {
+                // every cell moved over uses up fuel, on top of the scan itself
+                let most = self
+                    .limits
+                    .fuel
+                    .map_or(u64::MAX, |fuel| fuel.saturating_sub(self.steps + 1));
+                let mut moves = 0_u64;
+
+                while self.memory[self.memptr] != C::default() {
+                    // a wrapping tape without a zero cell never stops
+                    if self.scanned >= most {
+                        return Err(Limit::Fuel.into());
+                    }
+
+                    self.memptr = self.index(n)?;
+                    self.scanned += 1;
+
+                    moves += 1;
+                    if moves.is_multiple_of(CLOCK_INTERVAL)
+                        && self
+                            .deadline
+                            .is_some_and(|deadline| Instant::now() >= deadline)
+                    {
+                        return Err(Limit::Timeout.into());
                     }
                 }
-                _ => {}
+
+                // the fuel left went down by as much
+                self.next_check = self.next_check.saturating_sub(moves);
             }
+            _ => {}
+        }
+
+        self.advance();
 
-            self.instptr += 1;
+        Ok(())
+    }
+
+    /// Moves on to the next instruction, counting the one that just ran.
+    fn advance(&mut self) {
+        self.instptr += 1;
+        self.steps += 1;
+    }
+
+    /// The tape, with what the program left on it.
+    pub fn tape(&self) -> &Tape<C> {
+        &self.memory
+    }
+
+    /// Sets the cell at `position` to `value`, wrapped around at the cell width. The history
+    /// starts over from here, since what came before doesn't lead here anymore.
+    pub fn set_cell(&mut self, position: isize, value: u32) -> Result<()> {
+        if !self.memory.positions().contains(&position) {
+            return Err(eyre!("cell {position} isn't on the tape"));
+        }
+
+        self.memory[position] = C::from_value(value as i32);
+        if let Some(ref mut history) = self.history {
+            history.forget();
         }
+        self.refresh_watchpoints();
 
         Ok(())
     }
+
+    /// Position of the cell under the pointer.
+    pub fn memptr(&self) -> isize {
+        self.memptr
+    }
+
+    /// Index of the instruction that runs next.
+    pub fn instptr(&self) -> usize {
+        self.instptr
+    }
+
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    /// Position of the cell at `offset` from the pointer, applying the tape policy if it is past
+    /// either end.
+    fn index(&mut self, offset: isize) -> Result<isize> {
+        self.memory.resolve(self.memptr + offset)
+    }
 }
@@ -0,0 +1,129 @@
+use std::io::{
+    BufRead as _, BufReader, BufWriter, ErrorKind, IntoInnerError, Read, Result, Stdin, Stdout,
+    Write,
+};
+
+/// Input and output of a running program, both buffered.
+///
+/// Output is flushed whenever a read has to wait for more input, so prompts show up before the
+/// program blocks on the answer, and when the `Io` is dropped.
+#[derive(Debug)]
+pub struct Io<R: Read, W: Write> {
+    input: BufReader<R>,
+    output: BufWriter<W>,
+}
+
+/// Standard input and output of the process.
+pub type Stdio = Io<Stdin, Stdout>;
+
+impl Stdio {
+    pub fn stdio() -> Self {
+        Self::new(std::io::stdin(), std::io::stdout())
+    }
+}
+
+impl<R: Read, W: Write> Io<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Self {
+            input: BufReader::new(input),
+            output: BufWriter::new(output),
+        }
+    }
+
+    /// Reads a single byte, or `None` once the input is over.
+    pub fn read(&mut self) -> Result<Option<u8>> {
+        if self.input.buffer().is_empty() {
+            self.output.flush()?;
+        }
+
+        let mut byte = 0;
+        match self.input.read_exact(std::slice::from_mut(&mut byte)) {
+            Ok(()) => Ok(Some(byte)),
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Reads up to the end of the line, without the line break, or `None` once the input is
+    /// over.
+    pub fn read_line(&mut self) -> Result<Option<String>> {
+        if self.input.buffer().is_empty() {
+            self.output.flush()?;
+        }
+
+        let mut line = Vec::new();
+        if self.input.read_until(b'\n', &mut line)? == 0 {
+            return Ok(None);
+        }
+
+        if line.ends_with(b"\n") {
+            line.pop();
+        }
+        if line.ends_with(b"\r") {
+            line.pop();
+        }
+
+        Ok(Some(String::from_utf8_lossy(&line).into_owned()))
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.output.write_all(bytes)
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.output.flush()
+    }
+
+    /// The writer behind the output, without what is still in the buffer.
+    pub fn output_mut(&mut self) -> &mut W {
+        self.output.get_mut()
+    }
+
+    /// Flushes the output and gives back the writer behind it.
+    pub fn into_output(self) -> Result<W> {
+        self.output.into_inner().map_err(IntoInnerError::into_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read() {
+        let mut io = Io::new(&b"ab"[..], Vec::new());
+
+        assert_eq!(io.read().unwrap(), Some(b'a'));
+        assert_eq!(io.read().unwrap(), Some(b'b'));
+        assert_eq!(io.read().unwrap(), None);
+    }
+
+    #[test]
+    fn read_line() {
+        let mut io = Io::new(&b"step 2\r\n\nx"[..], Vec::new());
+
+        assert_eq!(io.read_line().unwrap().as_deref(), Some("step 2"));
+        assert_eq!(io.read_line().unwrap().as_deref(), Some(""));
+        assert_eq!(io.read().unwrap(), Some(b'x'));
+        assert_eq!(io.read_line().unwrap(), None);
+    }
+
+    #[test]
+    fn flushes_before_waiting() {
+        let mut io = Io::new(&b"ab"[..], Vec::new());
+
+        io.write(b"?").unwrap();
+        assert!(io.output_mut().is_empty());
+
+        // the input has to be read, so the prompt goes out first
+        io.read().unwrap();
+        assert_eq!(io.output_mut(), b"?");
+
+        // the next byte is already buffered
+        io.write(b"!").unwrap();
+        io.read().unwrap();
+        assert_eq!(io.output_mut(), b"?");
+
+        assert_eq!(io.into_output().unwrap(), b"?!");
+    }
+}
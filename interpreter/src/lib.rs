@@ -0,0 +1,25 @@
+pub use cell::Cell;
+pub use debugger::Debugger;
+pub use eval::{evaluate_prefix, Prefix};
+pub use interpreter::{Interpreter, StepResult};
+pub use io::{Io, Stdio};
+pub use limits::{parse_seconds, Limit, Limits};
+pub use profile::{LoopProfile, Profile};
+pub use state::{ExitReason, MachineState};
+pub use tape::Tape;
+pub use trace::{parse_source_range, parse_steps, TraceFormat, Tracer};
+pub use watch::{Comparison, Condition, Operand, Watchpoint};
+
+mod cell;
+mod debugger;
+mod eval;
+mod flame;
+mod history;
+mod interpreter;
+mod io;
+mod limits;
+mod profile;
+mod state;
+mod tape;
+mod trace;
+mod watch;
@@ -0,0 +1,67 @@
+use std::{fmt::Display, time::Duration};
+
+/// Bounds on how much a program gets to do, for running programs that can't be trusted.
+///
+/// The interpreter stops right at a limit. Compiled programs check fuel and time at the end of
+/// every loop iteration, so they can go a little past them, but never past the output limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Limits {
+    /// Most instructions to run, counting the ones run at compile time. Every cell a `[>]` scan
+    /// moves over uses up one more.
+    pub fuel: Option<u64>,
+    /// Longest the program gets to run. Time spent waiting for input counts, but waiting
+    /// itself isn't cut short.
+    pub timeout: Option<Duration>,
+    /// Most bytes to write, a `.` that would go past it isn't run.
+    pub output: Option<u64>,
+}
+
+/// Which of the [`Limits`] a program ran into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Limit {
+    Fuel,
+    Timeout,
+    Output,
+}
+
+impl Limit {
+    pub const fn code(&self) -> &'static str {
+        match self {
+            Self::Fuel => "fuel",
+            Self::Timeout => "timeout",
+            Self::Output => "output",
+        }
+    }
+}
+
+impl Display for Limit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Fuel => write!(f, "ran out of fuel"),
+            Self::Timeout => write!(f, "ran out of time"),
+            Self::Output => write!(f, "reached the output limit"),
+        }
+    }
+}
+
+impl std::error::Error for Limit {}
+
+/// Parses a number of seconds, like `2` or `0.5`.
+pub fn parse_seconds(s: &str) -> Result<Duration, String> {
+    s.parse::<f64>()
+        .map_err(|err| err.to_string())
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).map_err(|err| err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seconds() {
+        assert_eq!(parse_seconds("2"), Ok(Duration::from_secs(2)));
+        assert_eq!(parse_seconds("0.25"), Ok(Duration::from_millis(250)));
+        assert!(parse_seconds("-1").is_err());
+        assert!(parse_seconds("soon").is_err());
+    }
+}
@@ -1,12 +1,15 @@
 use std::sync::LazyLock;
 
 use clap::Parser as _;
-use cli::Cli;
-use color_eyre::{eyre::Context as _, Result};
-use parser::OptimizationOptions;
+use cli::{Cli, ProfileFormat};
+use color_eyre::{
+    eyre::{bail, ensure, Context as _},
+    Result,
+};
+use interpreter::{Cell, Debugger, ExitReason, Interpreter, Stdio, Tape, Tracer};
+use parser::{CellWidth, Instruction, PassManager, Span};
 
 mod cli;
-mod interpreter;
 
 pub static ARGS: LazyLock<Cli> = LazyLock::new(Cli::parse);
 
@@ -30,12 +33,114 @@ fn main() -> Result<()> {
     };
 
     let parser = parser::Parser::new(&source);
-    let instructions = parser
-        .parse(OptimizationOptions::all())
-        .wrap_err("failed to parse")?;
+    let instructions = match parser.parse_raw() {
+        Ok(instructions) => instructions,
+        Err(err) if ARGS.json_errors => {
+            eprintln!("{}", err.to_json());
+            std::process::exit(1);
+        }
+        Err(err) => return Err(err).wrap_err("failed to parse"),
+    };
+
+    let passes = PassManager::from(ARGS.opt_level);
+    let (instructions, spans) = passes
+        .run_inspect(instructions, |pass, instructions| {
+            if ARGS.dump_ir {
+                eprintln!("; after {pass}\n{}", parser::dump(instructions));
+            }
+        })
+        .into_iter()
+        .map(|instruction| (instruction.value, instruction.span))
+        .unzip();
+
+    if let Some(limit) = ARGS.memory_limit {
+        let bytes = ARGS.tape_size as usize * ARGS.cell_width.bytes() as usize;
+
+        ensure!(
+            bytes <= limit,
+            "tape of {} cells takes {bytes} bytes, over the memory limit of {limit} bytes",
+            ARGS.tape_size
+        );
+    }
+
+    match ARGS.cell_width {
+        CellWidth::U8 => run::<u8>(instructions, spans, &source),
+        CellWidth::U16 => run::<u16>(instructions, spans, &source),
+        CellWidth::U32 => run::<u32>(instructions, spans, &source),
+    }
+    .wrap_err("failed to interpret")?;
+
+    Ok(())
+}
+
+fn run<C: Cell>(instructions: Vec<Instruction>, spans: Vec<Span>, source: &[u8]) -> Result<()> {
+    let tape = Tape::new(ARGS.tape_size as usize, ARGS.tape_policy).with_limit(ARGS.memory_limit);
+
+    let mut interpreter = Interpreter::<C>::new(instructions)
+        .with_eof(ARGS.eof)
+        .with_output(ARGS.output_mode)
+        .with_tape(tape)
+        .with_limits(ARGS.limits());
+    if ARGS.profile.is_some() {
+        interpreter = interpreter.with_profile();
+    }
+
+    if ARGS.debug {
+        let interpreter = interpreter.with_history(ARGS.history);
+        let mut debugger = Debugger::new(interpreter, source, spans);
+        let state = debugger.run(&mut Stdio::stdio())?;
+
+        if ARGS.dump_state {
+            eprintln!("{}", state.to_json());
+        }
+
+        return Ok(());
+    }
+
+    let result = match ARGS.trace {
+        Some(format) => {
+            use std::io::{BufWriter, Write};
+
+            let out: Box<dyn Write> = match ARGS.trace_file {
+                Some(ref path) => {
+                    Box::new(std::fs::File::create(path).wrap_err("failed to create trace file")?)
+                }
+                None => Box::new(std::io::stderr()),
+            };
+            let mut tracer = Tracer::new(format, &spans, BufWriter::new(out));
+            if let Some(ref steps) = ARGS.trace_steps {
+                tracer = tracer.with_steps(steps.clone());
+            }
+            if let Some(ref source) = ARGS.trace_source {
+                tracer = tracer.with_source(source.clone());
+            }
+
+            interpreter.run_traced(std::io::stdin(), std::io::stdout(), &mut tracer)
+        }
+        None => interpreter.run_with(std::io::stdin(), std::io::stdout()),
+    };
+
+    if ARGS.dump_state {
+        eprintln!("{}", interpreter.state().to_json());
+    }
+
+    if let Some(profile) = interpreter.profile() {
+        let instructions = interpreter.instructions();
+
+        match ARGS.profile {
+            Some(ProfileFormat::Json) => eprintln!("{}", profile.to_json(instructions, &spans)),
+            Some(ProfileFormat::Folded) => eprint!("{}", profile.to_folded(instructions, &spans)),
+            Some(ProfileFormat::Chrome) => {
+                eprintln!("{}", profile.to_chrome_trace(instructions, &spans))
+            }
+            _ => eprint!("{}", profile.report(instructions, &spans)),
+        }
+    }
 
-    let interpreter = interpreter::Interpreter::<30_000>::new(instructions);
-    interpreter.run().wrap_err("failed to interpret")?;
+    let state = result?;
+    if let Some(ExitReason::LimitExceeded(limit)) = state.exit {
+        bail!("stopped after {} steps: {limit}", state.steps);
+    }
 
     Ok(())
 }
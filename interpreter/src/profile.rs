@@ -0,0 +1,270 @@
+use std::fmt::Write as _;
+
+use parser::{Instruction, Span};
+
+use crate::{Cell, Interpreter};
+
+/// Loops the text report shows, the JSON one has all of them.
+const HOTTEST: usize = 20;
+
+/// How many times each instruction ran, see [`Interpreter::with_profile`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Profile {
+    executions: Vec<u64>,
+}
+
+/// How a loop of the program ran, or the instruction it was folded into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LoopProfile {
+    /// Index of the `JumpIfZero`, or of the folded instruction.
+    pub start: usize,
+    /// Index of the matching `JumpIfNotZero`, the same as `start` for a folded instruction.
+    pub end: usize,
+    /// Times the program got to it, counting the times the loop was skipped.
+    pub entries: u64,
+    /// Times the body ran, `None` for a folded instruction, which runs it in a single step.
+    pub iterations: Option<u64>,
+    /// Instructions run inside, those of nested loops included.
+    pub steps: u64,
+}
+
+impl Profile {
+    pub(crate) fn new(instructions: usize) -> Self {
+        Self {
+            executions: vec![0; instructions],
+        }
+    }
+
+    /// Times each instruction ran, by index.
+    pub fn executions(&self) -> &[u64] {
+        &self.executions
+    }
+
+    /// Instructions run while profiling.
+    pub fn steps(&self) -> u64 {
+        self.executions.iter().sum()
+    }
+
+    /// Every loop of `instructions` and every `Multiply`, `Clear`, `Set` and `MoveUntilZero` a
+    /// loop was folded into, in program order.
+    pub fn loops(&self, instructions: &[Instruction]) -> Vec<LoopProfile> {
+        use Instruction::*;
+
+        instructions
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, instruction)| {
+                let entries = self.executions[idx];
+
+                match *instruction {
+                    JumpIfZero(end) => Some(LoopProfile {
+                        start: idx,
+                        end,
+                        entries,
+                        iterations: Some(self.executions[end]),
+                        steps: self.executions[idx..=end].iter().sum(),
+                    }),
+                    Multiply(..) | Clear(_) | Set(..) | MoveUntilZero(_) => Some(LoopProfile {
+                        start: idx,
+                        end: idx,
+                        entries,
+                        iterations: None,
+                        steps: entries,
+                    }),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    /// Loops that ran, hottest first, one per line along with where they are in the source.
+    pub fn report(&self, instructions: &[Instruction], spans: &[Span]) -> String {
+        let steps = self.steps();
+        let mut loops: Vec<_> = self
+            .loops(instructions)
+            .into_iter()
+            .filter(|profile| profile.entries != 0)
+            .collect();
+        loops.sort_by_key(|profile| (std::cmp::Reverse(profile.steps), profile.start));
+
+        let mut out = format!(
+            "; {steps} steps, {} loops ran\n{:>12} {:>6} {:>10} {:>12}  location\n",
+            loops.len(),
+            "steps",
+            "share",
+            "entries",
+            "iterations"
+        );
+        for profile in loops.iter().take(HOTTEST) {
+            let share = profile.steps as f64 * 100.0 / steps.max(1) as f64;
+            let iterations = profile
+                .iterations
+                .map_or("-".to_string(), |iterations| iterations.to_string());
+
+            // writing to a `String` never fails
+            write!(
+                out,
+                "{:>12} {share:>5.1}% {:>10} {iterations:>12}  ",
+                profile.steps, profile.entries,
+            )
+            .unwrap();
+            match instructions[profile.start] {
+                Instruction::JumpIfZero(_) => {
+                    writeln!(out, "{}-{} loop", spans[profile.start], spans[profile.end])
+                }
+                instruction => writeln!(out, "{} {instruction:?}", spans[profile.start]),
+            }
+            .unwrap();
+        }
+        if loops.len() > HOTTEST {
+            writeln!(out, "; {} more", loops.len() - HOTTEST).unwrap();
+        }
+
+        out
+    }
+
+    /// Every loop as JSON, in program order, with what the instruction it starts with is.
+    pub fn to_json(&self, instructions: &[Instruction], spans: &[Span]) -> String {
+        let mut out = format!(r#"{{"steps":{},"loops":["#, self.steps());
+
+        for (idx, profile) in self.loops(instructions).into_iter().enumerate() {
+            if idx != 0 {
+                out.push(',');
+            }
+
+            let kind = match instructions[profile.start] {
+                Instruction::JumpIfZero(_) => "loop",
+                Instruction::Multiply(..) => "multiply",
+                Instruction::Clear(_) => "clear",
+                Instruction::Set(..) => "set",
+                _ => "move_until_zero",
+            };
+            let (start, end) = (spans[profile.start], spans[profile.end]);
+            let iterations = profile
+                .iterations
+                .map_or("null".to_string(), |iterations| iterations.to_string());
+
+            write!(
+                out,
+                r#"{{"kind":"{kind}","start":{},"end":{},"line":{},"column":{},"end_line":{},"end_column":{},"entries":{},"iterations":{iterations},"steps":{}}}"#,
+                profile.start,
+                profile.end,
+                start.line,
+                start.column,
+                end.line,
+                end.column,
+                profile.entries,
+                profile.steps,
+            )
+            .unwrap(); // writing to a `String` never fails
+        }
+        out.push_str("]}");
+
+        out
+    }
+}
+
+impl<C: Cell> Interpreter<C> {
+    /// What the program ran so far, if it is profiled.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// Counts the instruction at `instptr`, which just ran.
+    pub(crate) fn count(&mut self, instptr: usize) {
+        if let Some(ref mut profile) = self.profile {
+            profile.executions[instptr] += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use parser::{OptimizationOptions, Parser};
+
+    /// Runs `source` with `ab` as input and profiles it.
+    pub(crate) fn profile(
+        source: &[u8],
+        opts: OptimizationOptions,
+    ) -> (Vec<Instruction>, Vec<Span>, Profile) {
+        let (instructions, spans): (Vec<_>, Vec<_>) = Parser::new(source)
+            .parse_spanned(opts)
+            .expect("failed to parse")
+            .into_iter()
+            .map(|instruction| (instruction.value, instruction.span))
+            .unzip();
+
+        let mut interpreter = Interpreter::<u8>::new(instructions.clone()).with_profile();
+        interpreter
+            .run_with(&b"ab"[..], Vec::new())
+            .expect("failed to run");
+        let profile = interpreter
+            .profile()
+            .expect("the program is profiled")
+            .clone();
+
+        (instructions, spans, profile)
+    }
+
+    #[test]
+    fn loops() {
+        let (instructions, _, profile) = profile(b"+++[>++[-]<-]>[<]", OptimizationOptions::new());
+
+        assert_eq!(profile.steps(), 39);
+        assert_eq!(
+            profile.loops(&instructions),
+            [
+                LoopProfile {
+                    start: 3,
+                    end: 12,
+                    entries: 1,
+                    iterations: Some(3),
+                    steps: 34,
+                },
+                LoopProfile {
+                    start: 7,
+                    end: 9,
+                    entries: 3,
+                    iterations: Some(6),
+                    steps: 15,
+                },
+                LoopProfile {
+                    start: 14,
+                    end: 16,
+                    entries: 1,
+                    iterations: Some(0),
+                    steps: 1,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn folded() {
+        let (instructions, spans, profile) =
+            profile(b",[>++<-]>.\n[-]+[>]", OptimizationOptions::all());
+
+        assert_eq!(
+            profile.report(&instructions, &spans),
+            "; 7 steps, 4 loops ran
+       steps  share    entries   iterations  location
+           1  14.3%          1            -  1:2 Multiply(0, 1, 2)
+           1  14.3%          1            -  1:2 Clear(0)
+           1  14.3%          1            -  2:1 Set(1, 1)
+           1  14.3%          1            -  2:5 MoveUntilZero(1)
+"
+        );
+        assert!(profile.to_json(&instructions, &spans).starts_with(
+            r#"{"steps":7,"loops":[{"kind":"multiply","start":1,"end":1,"line":1,"column":2,"end_line":1,"end_column":2,"entries":1,"iterations":null,"steps":1},"#
+        ));
+    }
+
+    #[test]
+    fn input() {
+        let (instructions, _, profile) = profile(b",[.,]", OptimizationOptions::new());
+
+        assert_eq!(profile.executions(), [1, 1, 2, 2, 2]);
+        assert_eq!(profile.loops(&instructions)[0].iterations, Some(2));
+    }
+}
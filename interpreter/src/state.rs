@@ -0,0 +1,128 @@
+use std::fmt::Write as _;
+
+use parser::escape_json;
+
+use crate::Limit;
+
+/// What a program left behind, the same for every backend.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MachineState {
+    /// Every cell, leftmost first, as values that fit the cell width.
+    pub tape: Vec<u32>,
+    /// Position of the first cell in `tape`. Positions count from the cell the program starts
+    /// on, so this is only negative if the tape grew to the left.
+    pub start: isize,
+    /// Position of the cell under the pointer.
+    pub memptr: isize,
+    /// Optimized instructions run so far, counting the ones run at compile time.
+    pub steps: u64,
+    /// Why the program stopped, `None` while it is still running.
+    pub exit: Option<ExitReason>,
+}
+
+/// Why a program stopped.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ExitReason {
+    /// Ran to the end.
+    Halted,
+    /// Stopped with an error, this is its message.
+    Error(String),
+    /// Stopped at one of the [`Limits`](crate::Limits), with what it wrote until then.
+    LimitExceeded(Limit),
+}
+
+impl MachineState {
+    /// Value of the cell at `position`, 0 for cells the tape never had.
+    pub fn cell(&self, position: isize) -> u32 {
+        usize::try_from(position - self.start)
+            .ok()
+            .and_then(|at| self.tape.get(at).copied())
+            .unwrap_or(0)
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut out = String::from(r#"{"tape":["#);
+
+        for (idx, value) in self.tape.iter().enumerate() {
+            if idx != 0 {
+                out.push(',');
+            }
+
+            write!(out, "{value}").unwrap(); // writing to a `String` never fails
+        }
+
+        write!(
+            out,
+            r#"],"start":{},"memptr":{},"steps":{},"exit":"#,
+            self.start, self.memptr, self.steps
+        )
+        .unwrap();
+
+        match self.exit {
+            None => out.push_str("null"),
+            Some(ExitReason::Halted) => out.push_str(r#"{"reason":"halted"}"#),
+            Some(ExitReason::Error(ref message)) => {
+                out.push_str(r#"{"reason":"error","message":""#);
+                escape_json(message, &mut out);
+                out.push_str(r#""}"#);
+            }
+            Some(ExitReason::LimitExceeded(limit)) => {
+                write!(
+                    out,
+                    r#"{{"reason":"limit_exceeded","limit":"{}"}}"#,
+                    limit.code()
+                )
+                .unwrap();
+            }
+        }
+        out.push('}');
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cell() {
+        let state = MachineState {
+            tape: vec![1, 2, 3],
+            start: -1,
+            memptr: 0,
+            steps: 0,
+            exit: None,
+        };
+
+        assert_eq!(state.cell(-1), 1);
+        assert_eq!(state.cell(1), 3);
+        assert_eq!(state.cell(-2), 0);
+        assert_eq!(state.cell(2), 0);
+    }
+
+    #[test]
+    fn json() {
+        let mut state = MachineState {
+            tape: vec![0, 72],
+            start: 0,
+            memptr: 1,
+            steps: 4,
+            exit: Some(ExitReason::Halted),
+        };
+        assert_eq!(
+            state.to_json(),
+            r#"{"tape":[0,72],"start":0,"memptr":1,"steps":4,"exit":{"reason":"halted"}}"#
+        );
+
+        state.exit = Some(ExitReason::Error("no \"input\"".to_string()));
+        assert!(state
+            .to_json()
+            .ends_with(r#""exit":{"reason":"error","message":"no \"input\""}}"#));
+
+        state.exit = Some(ExitReason::LimitExceeded(Limit::Fuel));
+        assert!(state
+            .to_json()
+            .ends_with(r#""exit":{"reason":"limit_exceeded","limit":"fuel"}}"#));
+    }
+}
@@ -0,0 +1,225 @@
+use std::ops::{Index, IndexMut, Range, RangeInclusive};
+
+use color_eyre::{eyre::eyre, Result};
+
+use parser::{TapePolicy, DEFAULT_TAPE_SIZE};
+
+use crate::Cell;
+
+/// Cells of the interpreter, kept on the heap.
+///
+/// Cells are addressed relative to the one the program starts on, so under
+/// [`TapePolicy::Grow`] the tape can grow to the left and cells end up at negative positions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tape<C: Cell> {
+    cells: Vec<C>,
+    /// Index in `cells` of cell 0, it moves right whenever the tape grows to the left.
+    origin: usize,
+    policy: TapePolicy,
+    /// Most bytes the cells can take up, if there's a limit.
+    limit: Option<usize>,
+    grows_left: bool,
+    /// Lowest and highest cells reached so far.
+    lowest: isize,
+    highest: isize,
+}
+
+impl<C: Cell> Tape<C> {
+    /// Makes a tape of `size` cells, what happens past them is up to `policy`.
+    pub fn new(size: usize, policy: TapePolicy) -> Self {
+        Self {
+            cells: vec![C::default(); size],
+            origin: 0,
+            policy,
+            limit: None,
+            grows_left: true,
+            lowest: 0,
+            highest: 0,
+        }
+    }
+
+    /// Stops the tape from growing past `bytes`. The cells it starts with are always there.
+    pub fn with_limit(mut self, bytes: Option<usize>) -> Self {
+        self.limit = bytes;
+
+        self
+    }
+
+    /// Makes going left of cell 0 an error under [`TapePolicy::Grow`], the way compiled
+    /// programs handle it.
+    pub(crate) fn right_only(mut self) -> Self {
+        self.grows_left = false;
+
+        self
+    }
+
+    /// Where the cell at `at` ends up after applying the tape policy, growing the tape if it
+    /// has to.
+    pub fn resolve(&mut self, at: isize) -> Result<isize> {
+        let at = match self.policy {
+            TapePolicy::Wrap => at.rem_euclid(self.cells.len() as isize),
+            _ if self.positions().contains(&at) => at,
+            TapePolicy::Grow if at >= 0 || self.grows_left => {
+                self.grow(at)?;
+
+                at
+            }
+            _ => return Err(eyre!("pointer moved out of the tape to cell {at}")),
+        };
+
+        self.lowest = self.lowest.min(at);
+        self.highest = self.highest.max(at);
+
+        Ok(at)
+    }
+
+    /// Where the cell at `at` is after wrapping around, without growing the tape or checking
+    /// that it is there.
+    pub(crate) fn position(&self, at: isize) -> isize {
+        match self.policy {
+            TapePolicy::Wrap => at.rem_euclid(self.cells.len() as isize),
+            _ => at,
+        }
+    }
+
+    /// Lowest and highest cells the program reached, its high-water marks.
+    pub fn reached(&self) -> RangeInclusive<isize> {
+        self.lowest..=self.highest
+    }
+
+    /// Positions of the cells there are right now.
+    pub fn positions(&self) -> Range<isize> {
+        let start = -(self.origin as isize);
+
+        start..start + self.cells.len() as isize
+    }
+
+    /// All the cells, starting with the leftmost one.
+    pub fn cells(&self) -> &[C] {
+        &self.cells
+    }
+
+    /// Makes the tape long enough to have a cell at `at`, at least doubling it so growing one
+    /// cell at a time stays cheap.
+    fn grow(&mut self, at: isize) -> Result<()> {
+        let positions = self.positions();
+        let length = self.cells.len();
+        let needed = if at < positions.start {
+            positions.start.abs_diff(at)
+        } else {
+            at.abs_diff(positions.end) + 1
+        };
+
+        let mut extra = needed.max(length);
+        if let Some(limit) = self.limit {
+            let bytes = C::WIDTH.bytes() as usize;
+            let room = (limit / bytes).saturating_sub(length);
+
+            if needed > room {
+                return Err(eyre!(
+                    "tape would take {} bytes to reach cell {at}, over the limit of {limit} bytes \
+                     (cells {} to {} were reached)",
+                    (length + needed) * bytes,
+                    self.lowest,
+                    self.highest,
+                ));
+            }
+
+            extra = extra.min(room);
+        }
+
+        if at < positions.start {
+            self.cells
+                .splice(0..0, std::iter::repeat_n(C::default(), extra));
+            self.origin += extra;
+        } else {
+            self.cells.resize(length + extra, C::default());
+        }
+
+        Ok(())
+    }
+}
+
+impl<C: Cell> Default for Tape<C> {
+    fn default() -> Self {
+        Self::new(DEFAULT_TAPE_SIZE, TapePolicy::default())
+    }
+}
+
+impl<C: Cell> Index<isize> for Tape<C> {
+    type Output = C;
+
+    fn index(&self, at: isize) -> &C {
+        &self.cells[(self.origin as isize + at) as usize]
+    }
+}
+
+impl<C: Cell> IndexMut<isize> for Tape<C> {
+    fn index_mut(&mut self, at: isize) -> &mut C {
+        &mut self.cells[(self.origin as isize + at) as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap() {
+        let mut tape = Tape::<u8>::new(4, TapePolicy::Wrap);
+
+        assert_eq!(tape.resolve(-1).unwrap(), 3);
+        assert_eq!(tape.resolve(9).unwrap(), 1);
+        assert_eq!(tape.cells().len(), 4);
+    }
+
+    #[test]
+    fn error() {
+        let mut tape = Tape::<u8>::new(4, TapePolicy::Error);
+
+        assert_eq!(tape.resolve(3).unwrap(), 3);
+        assert!(tape.resolve(4).is_err());
+        assert!(tape.resolve(-1).is_err());
+    }
+
+    #[test]
+    fn grows_both_ways() {
+        let mut tape = Tape::<u16>::new(4, TapePolicy::Grow);
+
+        tape[1] = 7;
+        assert_eq!(tape.resolve(5).unwrap(), 5);
+        assert_eq!(tape.positions(), 0..8);
+
+        assert_eq!(tape.resolve(-2).unwrap(), -2);
+        tape[-2] = 3;
+        assert_eq!(tape.positions(), -8..8);
+        assert_eq!(tape[1], 7);
+        assert_eq!(tape[-2], 3);
+        assert_eq!(tape.cells()[6], 3);
+
+        assert_eq!(tape.reached(), -2..=5);
+    }
+
+    #[test]
+    fn right_only() {
+        let mut tape = Tape::<u8>::new(4, TapePolicy::Grow).right_only();
+
+        assert_eq!(tape.resolve(4).unwrap(), 4);
+        assert!(tape.resolve(-1).is_err());
+    }
+
+    #[test]
+    fn limit() {
+        let mut tape = Tape::<u16>::new(4, TapePolicy::Grow).with_limit(Some(14));
+
+        // doubling would take 16 bytes, only 14 are allowed
+        assert_eq!(tape.resolve(4).unwrap(), 4);
+        assert_eq!(tape.positions(), 0..7);
+
+        assert_eq!(
+            tape.resolve(-1).unwrap_err().to_string(),
+            "tape would take 16 bytes to reach cell -1, over the limit of 14 bytes (cells 0 to 4 \
+             were reached)"
+        );
+    }
+}
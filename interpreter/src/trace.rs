@@ -0,0 +1,376 @@
+use std::{
+    fmt::Write as _,
+    io::{Read, Write},
+    ops::{Range, RangeInclusive},
+    str::FromStr,
+};
+
+use color_eyre::{eyre::Context, Result};
+
+use parser::{Instruction, Span};
+
+use crate::{Cell, Interpreter, Io, MachineState};
+
+/// How a [`Tracer`] writes every step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TraceFormat {
+    /// A line per step, for people to read.
+    #[default]
+    Text,
+    /// A JSON object per line.
+    Jsonl,
+    /// 44 bytes per step, every field little-endian: the step as a `u64`, the instruction and
+    /// the byte range it was parsed from as three `u32`s, the pointer and the position of the
+    /// cell as two `i64`s and the cell before and after as two `u32`s.
+    Binary,
+}
+
+/// What a single step did, the cell being the one it wrote or else the one under the pointer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct TraceRecord {
+    /// Steps before this one.
+    step: u64,
+    instruction: usize,
+    memptr: isize,
+    cell: isize,
+    before: u32,
+    after: u32,
+}
+
+/// Writes a record of every step of a program, see [`Interpreter::run_traced`].
+#[derive(Debug)]
+pub struct Tracer<'a, W: Write> {
+    format: TraceFormat,
+    /// Where every instruction was parsed from.
+    spans: &'a [Span],
+    steps: Range<u64>,
+    /// Lines and columns the instructions to trace start between.
+    source: RangeInclusive<(usize, usize)>,
+    out: W,
+    /// What went wrong writing the trace, the program isn't stopped right away.
+    error: Option<std::io::Error>,
+}
+
+impl<'a, W: Write> Tracer<'a, W> {
+    pub fn new(format: TraceFormat, spans: &'a [Span], out: W) -> Self {
+        Self {
+            format,
+            spans,
+            steps: 0..u64::MAX,
+            source: (0, 0)..=(usize::MAX, usize::MAX),
+            out,
+            error: None,
+        }
+    }
+
+    /// Only traces steps in `steps`, counting from 0. The program runs faster outside of them.
+    pub fn with_steps(mut self, steps: Range<u64>) -> Self {
+        self.steps = steps;
+
+        self
+    }
+
+    /// Only traces instructions whose span starts between these lines and columns.
+    pub fn with_source(mut self, source: RangeInclusive<(usize, usize)>) -> Self {
+        self.source = source;
+
+        self
+    }
+
+    /// Writes the step `interpreter` just took, unless it is filtered out.
+    fn record<C: Cell>(&mut self, interpreter: &Interpreter<C>) {
+        let Some(record) = interpreter.traced else {
+            return;
+        };
+        let span = self.spans[record.instruction];
+        if self.error.is_some()
+            || !self.steps.contains(&record.step)
+            || !self.source.contains(&(span.line, span.column))
+        {
+            return;
+        }
+
+        let instruction = interpreter.instructions()[record.instruction];
+        if let Err(err) = self.write(&record, span, instruction) {
+            self.error = Some(err);
+        }
+    }
+
+    fn write(
+        &mut self,
+        record: &TraceRecord,
+        span: Span,
+        instruction: Instruction,
+    ) -> std::io::Result<()> {
+        let TraceRecord {
+            step,
+            instruction: idx,
+            memptr,
+            cell,
+            before,
+            after,
+        } = *record;
+
+        match self.format {
+            TraceFormat::Text => writeln!(
+                self.out,
+                "{step} {idx} {span} {instruction:?} ptr {memptr} cell[{cell}] {before} -> {after}"
+            ),
+            TraceFormat::Jsonl => {
+                let mut line = String::new();
+
+                // writing to a `String` never fails
+                writeln!(
+                    line,
+                    r#"{{"step":{step},"instruction":{idx},"start":{},"end":{},"line":{},"column":{},"memptr":{memptr},"cell":{cell},"before":{before},"after":{after}}}"#,
+                    span.start, span.end, span.line, span.column,
+                )
+                .unwrap();
+
+                self.out.write_all(line.as_bytes())
+            }
+            TraceFormat::Binary => {
+                let mut bytes = Vec::with_capacity(44);
+
+                bytes.extend(step.to_le_bytes());
+                for value in [idx, span.start, span.end] {
+                    bytes.extend((value as u32).to_le_bytes());
+                }
+                for value in [memptr, cell] {
+                    bytes.extend((value as i64).to_le_bytes());
+                }
+                for value in [before, after] {
+                    bytes.extend(value.to_le_bytes());
+                }
+
+                self.out.write_all(&bytes)
+            }
+        }
+    }
+
+    /// Flushes the trace, failing if anything couldn't be written.
+    fn finish(&mut self) -> Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err).wrap_err("failed to write trace");
+        }
+
+        self.out.flush().wrap_err("failed to write trace")
+    }
+}
+
+impl<C: Cell> Interpreter<C> {
+    /// Same as [`Interpreter::run_with`], writing every step to `tracer` as well.
+    pub fn run_traced<W: Write>(
+        &mut self,
+        input: impl Read,
+        output: impl Write,
+        tracer: &mut Tracer<W>,
+    ) -> Result<MachineState> {
+        let mut io = Io::new(input, output);
+
+        let result = self.run_tracing(&mut io, tracer);
+        self.tracing(false);
+        let flushed = io.flush().wrap_err("failed to write output");
+        result?;
+        self.check(flushed)?;
+        tracer.finish()?;
+
+        Ok(self.state())
+    }
+
+    /// Runs the program up to the steps `tracer` wants, traced, then untraced again.
+    fn run_tracing<R: Read, W: Write>(
+        &mut self,
+        io: &mut Io<R, W>,
+        tracer: &mut Tracer<impl Write>,
+    ) -> Result<()> {
+        let Range { start, end } = tracer.steps.clone();
+
+        if self.steps < start {
+            self.run_until(io, |interpreter| interpreter.steps >= start)?;
+        }
+
+        self.tracing(true);
+        self.run_until(io, |interpreter| {
+            tracer.record(interpreter);
+
+            interpreter.steps >= end || tracer.error.is_some()
+        })?;
+        self.tracing(false);
+
+        if tracer.error.is_none() {
+            self.run_until(io, |_| false)?;
+        }
+
+        Ok(())
+    }
+
+    fn tracing(&mut self, tracing: bool) {
+        self.tracing = tracing;
+        self.traced = None;
+        self.next_check = 0;
+    }
+
+    /// What the next step is about to change, if the program is traced.
+    pub(crate) fn trace_before(&self) -> Option<TraceRecord> {
+        if !self.tracing {
+            return None;
+        }
+
+        let cell = self.target().unwrap_or(self.memptr);
+
+        Some(TraceRecord {
+            step: self.steps,
+            instruction: self.instptr,
+            memptr: self.memptr,
+            cell,
+            before: self.cell(cell),
+            after: 0,
+        })
+    }
+
+    /// Takes in what the step that just ran changed.
+    pub(crate) fn trace_after(&mut self, record: Option<TraceRecord>) {
+        self.traced = record.map(|record| TraceRecord {
+            after: self.cell(record.cell),
+            ..record
+        });
+    }
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "jsonl" => Ok(Self::Jsonl),
+            "binary" => Ok(Self::Binary),
+            _ => Err(format!(
+                "unknown trace format '{s}', expected text, jsonl or binary"
+            )),
+        }
+    }
+}
+
+/// Parses a range of steps, like `1000..2000`, `1000..` or `..2000`.
+pub fn parse_steps(s: &str) -> Result<Range<u64>, String> {
+    let (start, end) = s
+        .split_once("..")
+        .ok_or_else(|| format!("expected a range like 1000..2000, got '{s}'"))?;
+    let parse = |value: &str, default| match value {
+        "" => Ok(default),
+        value => value.parse().map_err(|err| format!("{err} in '{s}'")),
+    };
+
+    Ok(parse(start, 0)?..parse(end, u64::MAX)?)
+}
+
+/// Parses a range of lines, like `3..5`, or of lines and columns, like `3:14..5:2`. Both ends
+/// are included, a line without a column being all of it.
+pub fn parse_source_range(s: &str) -> Result<RangeInclusive<(usize, usize)>, String> {
+    let (start, end) = s
+        .split_once("..")
+        .ok_or_else(|| format!("expected a range like 3..5 or 3:14..5:2, got '{s}'"))?;
+    let parse = |location: &str, column| {
+        let (line, column) = match location.split_once(':') {
+            Some((line, column)) => (line, column.parse()),
+            None => (location, Ok(column)),
+        };
+
+        line.parse()
+            .and_then(|line| Ok((line, column?)))
+            .map_err(|_| format!("expected a range like 3..5 or 3:14..5:2, got '{s}'"))
+    };
+
+    Ok(parse(start, 0)?..=parse(end, usize::MAX)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::{OptimizationOptions, Parser};
+
+    const SOURCE: &[u8] = b",>++\n[-<+>]<.";
+
+    fn parse(source: &[u8]) -> (Vec<Instruction>, Vec<Span>) {
+        Parser::new(source)
+            .parse_spanned(OptimizationOptions::all())
+            .expect("failed to parse")
+            .into_iter()
+            .map(|instruction| (instruction.value, instruction.span))
+            .unzip()
+    }
+
+    fn run<W: Write>(instructions: Vec<Instruction>, tracer: &mut Tracer<W>) {
+        Interpreter::<u8>::new(instructions)
+            .run_traced(&b"a"[..], Vec::new(), tracer)
+            .expect("failed to run");
+    }
+
+    #[test]
+    fn text() {
+        let (instructions, spans) = parse(SOURCE);
+        let mut out = Vec::new();
+
+        run(
+            instructions,
+            &mut Tracer::new(TraceFormat::Text, &spans, &mut out),
+        );
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "0 0 1:1 In(0) ptr 0 cell[0] 0 -> 97
+1 1 1:3 Add(1, 2) ptr 0 cell[1] 0 -> 2
+2 2 2:1 Multiply(1, 0, 1) ptr 0 cell[0] 97 -> 99
+3 3 2:1 Clear(1) ptr 0 cell[1] 2 -> 0
+4 4 2:8 Out(0) ptr 0 cell[0] 99 -> 99
+"
+        );
+    }
+
+    #[test]
+    fn filters() {
+        let (instructions, spans) = parse(SOURCE);
+        let mut out = Vec::new();
+
+        run(
+            instructions,
+            &mut Tracer::new(TraceFormat::Jsonl, &spans, &mut out)
+                .with_steps(1..4)
+                .with_source((2, 0)..=(2, usize::MAX)),
+        );
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"{"step":2,"instruction":2,"start":5,"end":11,"line":2,"column":1,"memptr":0,"cell":0,"before":97,"after":99}
+{"step":3,"instruction":3,"start":5,"end":11,"line":2,"column":1,"memptr":0,"cell":1,"before":2,"after":0}
+"#
+        );
+    }
+
+    #[test]
+    fn binary() {
+        let (instructions, spans) = parse(b"+");
+        let mut out = Vec::new();
+
+        run(
+            instructions,
+            &mut Tracer::new(TraceFormat::Binary, &spans, &mut out),
+        );
+        assert_eq!(out.len(), 44);
+        assert_eq!(out[..8], 0_u64.to_le_bytes());
+        assert_eq!(out[16..20], 1_u32.to_le_bytes());
+        assert_eq!(out[36..], [0, 0, 0, 0, 1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(parse_steps("10..20"), Ok(10..20));
+        assert_eq!(parse_steps("10.."), Ok(10..u64::MAX));
+        assert_eq!(parse_steps("..20"), Ok(0..20));
+        assert!(parse_steps("10").is_err());
+
+        assert_eq!(parse_source_range("3..5"), Ok((3, 0)..=(5, usize::MAX)));
+        assert_eq!(parse_source_range("3:14..5:2"), Ok((3, 14)..=(5, 2)));
+        assert!(parse_source_range("3:x..5").is_err());
+    }
+}
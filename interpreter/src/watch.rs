@@ -0,0 +1,424 @@
+use std::{fmt::Display, str::FromStr};
+
+use color_eyre::{eyre::eyre, Report, Result};
+
+use crate::{Cell, Interpreter, StepResult};
+
+/// Where in a program, or in what state, to stop it, see [`Interpreter::watch`].
+///
+/// Without an instruction, a comparison stops the program where it starts to hold, not at every
+/// step after.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Watchpoint {
+    /// Only stop right before this instruction.
+    pub instruction: Option<usize>,
+    /// Only stop if this holds.
+    pub condition: Option<Condition>,
+}
+
+/// Something about the tape or the pointer a [`Watchpoint`] waits for.
+///
+/// Parses from `cell[42]` for the cell at 42 changing, or from a comparison like `ptr > 1000`,
+/// `cell == 10` for the cell under the pointer or `cell[42] != 0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Condition {
+    /// The cell at this position got a different value.
+    Changed(isize),
+    Compare(Operand, Comparison, i64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operand {
+    /// Position of the cell under the pointer.
+    Pointer,
+    /// Value of the cell under the pointer.
+    Cell,
+    /// Value of the cell at this position.
+    CellAt(isize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+/// A [`Watchpoint`] an interpreter looks at after every step.
+#[derive(Debug, Clone)]
+pub(crate) struct Watch {
+    id: usize,
+    watchpoint: Watchpoint,
+    /// Value the cell it waits to change had after the last step.
+    value: u32,
+    /// Whether its comparison held after the last step.
+    held: bool,
+}
+
+impl Comparison {
+    const SYMBOLS: [(&str, Self); 6] = [
+        ("==", Self::Equal),
+        ("!=", Self::NotEqual),
+        ("<=", Self::LessOrEqual),
+        (">=", Self::GreaterOrEqual),
+        ("<", Self::Less),
+        (">", Self::Greater),
+    ];
+
+    pub fn holds(&self, left: i64, right: i64) -> bool {
+        match self {
+            Self::Equal => left == right,
+            Self::NotEqual => left != right,
+            Self::Less => left < right,
+            Self::LessOrEqual => left <= right,
+            Self::Greater => left > right,
+            Self::GreaterOrEqual => left >= right,
+        }
+    }
+}
+
+impl Watchpoint {
+    /// Whether the program is right before the watchpoint's instruction and its comparison
+    /// holds. Whether a cell changed can't be told from the state alone, so it isn't looked at.
+    pub(crate) fn matches<C: Cell>(&self, interpreter: &Interpreter<C>) -> bool {
+        if self
+            .instruction
+            .is_some_and(|instruction| instruction != interpreter.instptr)
+        {
+            return false;
+        }
+
+        match self.condition {
+            Some(Condition::Compare(operand, comparison, value)) => {
+                let left = match operand {
+                    Operand::Pointer => interpreter.memptr as i64,
+                    Operand::Cell => interpreter.cell(interpreter.memptr) as i64,
+                    Operand::CellAt(position) => interpreter.cell(position) as i64,
+                };
+
+                comparison.holds(left, value)
+            }
+            Some(Condition::Changed(_)) | None => true,
+        }
+    }
+}
+
+impl<C: Cell> Interpreter<C> {
+    /// Stops [`Interpreter::run_until`] after any step that leaves the program where
+    /// `watchpoint` holds. Returns its ID, for [`Interpreter::watchpoint_hit`] and
+    /// [`Interpreter::unwatch`].
+    pub fn watch(&mut self, watchpoint: Watchpoint) -> usize {
+        let id = self.next_watch_id;
+        self.next_watch_id += 1;
+
+        self.watchpoints.push(Watch {
+            id,
+            watchpoint,
+            value: 0,
+            held: false,
+        });
+        self.refresh_watchpoints();
+        self.next_check = 0;
+
+        id
+    }
+
+    pub fn unwatch(&mut self, id: usize) -> Option<Watchpoint> {
+        let idx = self.watchpoints.iter().position(|watch| watch.id == id)?;
+        self.hit = None;
+
+        Some(self.watchpoints.remove(idx).watchpoint)
+    }
+
+    /// All the watchpoints with their IDs, oldest first.
+    pub fn watchpoints(&self) -> impl Iterator<Item = (usize, &Watchpoint)> {
+        self.watchpoints
+            .iter()
+            .map(|watch| (watch.id, &watch.watchpoint))
+    }
+
+    /// ID of the watchpoint that held after the last step, the oldest one if more did.
+    pub fn watchpoint_hit(&self) -> Option<usize> {
+        self.hit
+    }
+
+    /// Same as [`Interpreter::step`] past the limits, looking at the watchpoints after it.
+    pub(crate) fn step_watched(&mut self) -> Result<StepResult> {
+        let steps = self.steps;
+
+        self.hit = None;
+        let result = match self.history {
+            Some(_) => self.step_recorded(),
+            None => self.execute_next(),
+        };
+        if self.steps > steps {
+            self.look_at_watchpoints();
+        }
+
+        result
+    }
+
+    /// Same as [`Interpreter::input`], looking at the watchpoints after it.
+    pub(crate) fn input_watched(&mut self, offset: isize, byte: Option<u8>) -> Result<()> {
+        self.hit = None;
+        let result = match self.history {
+            Some(_) => self.input_recorded(offset, byte),
+            None => {
+                let result = self.read(offset, byte);
+                self.check(result)
+            }
+        };
+        if result.is_ok() {
+            self.look_at_watchpoints();
+        }
+
+        result
+    }
+
+    /// Takes in the state as it is now, so only what changes after this counts.
+    pub(crate) fn refresh_watchpoints(&mut self) {
+        self.hit = None;
+
+        for idx in 0..self.watchpoints.len() {
+            let (value, held) = self.look_at(&self.watchpoints[idx].watchpoint);
+
+            self.watchpoints[idx].value = value;
+            self.watchpoints[idx].held = held;
+        }
+    }
+
+    /// Value of the cell at `position`, a cell the tape didn't grow to yet being zero.
+    pub(crate) fn cell(&self, position: isize) -> u32 {
+        let position = self.memory.position(position);
+
+        match self.memory.positions().contains(&position) {
+            true => self.memory[position].to_value(),
+            false => 0,
+        }
+    }
+
+    fn look_at_watchpoints(&mut self) {
+        for idx in 0..self.watchpoints.len() {
+            let watch = &self.watchpoints[idx];
+            let (value, held) = self.look_at(&watch.watchpoint);
+
+            let hit = match watch.watchpoint {
+                Watchpoint {
+                    condition: Some(Condition::Changed(_)),
+                    ..
+                } => held && value != watch.value,
+                Watchpoint {
+                    instruction: None,
+                    condition: Some(_),
+                } => held && !watch.held,
+                _ => held,
+            };
+            if hit && self.hit.is_none() {
+                self.hit = Some(watch.id);
+            }
+
+            // every watchpoint takes in the step, even once one of them was hit
+            self.watchpoints[idx].value = value;
+            self.watchpoints[idx].held = held;
+        }
+    }
+
+    /// Value of the cell `watchpoint` waits to change, and whether the rest of it holds.
+    fn look_at(&self, watchpoint: &Watchpoint) -> (u32, bool) {
+        let value = match watchpoint.condition {
+            Some(Condition::Changed(position)) => self.cell(position),
+            _ => 0,
+        };
+
+        (value, watchpoint.matches(self))
+    }
+}
+
+impl Display for Watchpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.instruction, self.condition) {
+            (Some(instruction), Some(condition)) => {
+                write!(f, "at instruction {instruction} if {condition}")
+            }
+            (Some(instruction), None) => write!(f, "at instruction {instruction}"),
+            (None, Some(condition)) => write!(f, "{condition}"),
+            (None, None) => write!(f, "every step"),
+        }
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Changed(position) => write!(f, "cell[{position}] changes"),
+            Self::Compare(operand, comparison, value) => {
+                write!(f, "{operand} {comparison} {value}")
+            }
+        }
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Pointer => write!(f, "ptr"),
+            Self::Cell => write!(f, "cell"),
+            Self::CellAt(position) => write!(f, "cell[{position}]"),
+        }
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (symbol, _) = Self::SYMBOLS
+            .iter()
+            .find(|(_, comparison)| comparison == self)
+            .expect("every comparison has a symbol");
+
+        write!(f, "{symbol}")
+    }
+}
+
+impl FromStr for Condition {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let expected = || eyre!("expected 'cell[N]' or a comparison like 'ptr > N', got '{s}'");
+
+        let s = s.trim();
+        let (operand, rest) = if let Some(rest) = s.strip_prefix("ptr") {
+            (Operand::Pointer, rest)
+        } else if let Some(rest) = s.strip_prefix("cell[") {
+            let (position, rest) = rest.split_once(']').ok_or_else(expected)?;
+            let position = position.trim().parse().map_err(|_| expected())?;
+
+            (Operand::CellAt(position), rest)
+        } else if let Some(rest) = s.strip_prefix("cell") {
+            (Operand::Cell, rest)
+        } else {
+            return Err(expected());
+        };
+
+        let rest = rest.trim();
+        if rest.is_empty() {
+            return match operand {
+                Operand::CellAt(position) => Ok(Self::Changed(position)),
+                _ => Err(expected()),
+            };
+        }
+
+        let (symbol, comparison) = Comparison::SYMBOLS
+            .into_iter()
+            .find(|(symbol, _)| rest.starts_with(symbol))
+            .ok_or_else(expected)?;
+        let value = rest[symbol.len()..]
+            .trim()
+            .parse()
+            .map_err(|_| expected())?;
+
+        Ok(Self::Compare(operand, comparison, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Io;
+    use parser::Parser;
+
+    fn interpreter(source: &[u8]) -> Interpreter<u8> {
+        let instructions = Parser::new(source)
+            .parse_raw()
+            .expect("failed to parse")
+            .into_iter()
+            .map(|instruction| instruction.value)
+            .collect();
+
+        Interpreter::new(instructions)
+    }
+
+    fn condition(s: &str) -> Option<Condition> {
+        Some(s.parse().unwrap())
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(condition("cell[-3]"), Some(Condition::Changed(-3)));
+        assert_eq!(
+            condition("ptr>1000"),
+            Some(Condition::Compare(
+                Operand::Pointer,
+                Comparison::Greater,
+                1000
+            ))
+        );
+        assert_eq!(
+            condition(" cell[2]<=-1"),
+            Some(Condition::Compare(
+                Operand::CellAt(2),
+                Comparison::LessOrEqual,
+                -1
+            ))
+        );
+        assert_eq!(
+            condition("cell != 0").unwrap().to_string(),
+            "cell != 0".to_string()
+        );
+
+        for bad in ["cell", "ptr", "ptr = 1", "tape[1]", "cell[x]", "cell > y"] {
+            assert!(bad.parse::<Condition>().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn watch() {
+        let mut interpreter = interpreter(b"+>++>+++<<[>]");
+        let changed = interpreter.watch(Watchpoint {
+            instruction: None,
+            condition: condition("cell[1]"),
+        });
+        let pointer = interpreter.watch(Watchpoint {
+            instruction: None,
+            condition: condition("ptr >= 2"),
+        });
+        let at = interpreter.watch(Watchpoint {
+            instruction: Some(10),
+            condition: condition("cell == 1"),
+        });
+
+        let mut io = Io::new(&b""[..], Vec::new());
+        let mut hits = Vec::new();
+        while !interpreter.run_until(&mut io, |_| false).unwrap() {
+            match interpreter.watchpoint_hit() {
+                Some(id) => hits.push((id, interpreter.steps)),
+                None => break,
+            }
+        }
+
+        assert_eq!(
+            hits,
+            [
+                (changed, 3),
+                (changed, 4),
+                (pointer, 5),
+                (at, 10),
+                (pointer, 14)
+            ]
+        );
+
+        assert!(interpreter.unwatch(changed).is_some());
+        assert!(interpreter.unwatch(changed).is_none());
+        assert_eq!(interpreter.watchpoints().count(), 2);
+
+        // the newest one's ID isn't handed out again either
+        assert!(interpreter.unwatch(at).is_some());
+        let next = interpreter.watch(Watchpoint {
+            instruction: None,
+            condition: None,
+        });
+        assert_eq!(next, at + 1);
+    }
+}
@@ -0,0 +1,130 @@
+//! Embeds the interpreter, feeding input from memory and capturing output.
+
+use std::time::Duration;
+
+use interpreter::{ExitReason, Interpreter, Io, Limit, Limits, StepResult, Tape};
+use parser::{OptimizationOptions, Parser, TapePolicy};
+
+fn interpreter(source: &[u8]) -> Interpreter<u8> {
+    let instructions = Parser::new(source)
+        .parse(OptimizationOptions::all())
+        .expect("failed to parse");
+
+    Interpreter::new(instructions)
+}
+
+#[test]
+fn run_with() {
+    let mut interpreter = interpreter(b",[+.,]");
+    let mut output = Vec::new();
+
+    let state = interpreter
+        .run_with(&b"HAL"[..], &mut output)
+        .expect("failed to run");
+
+    assert_eq!(output, b"IBM");
+    assert!(interpreter.is_done());
+    assert_eq!(state.cell(0), 0);
+    assert_eq!(state.steps, 14);
+    assert_eq!(state.exit, Some(ExitReason::Halted));
+}
+
+#[test]
+fn state() {
+    let mut interpreter =
+        interpreter(b"+<+<-").with_tape(Tape::new(4, TapePolicy::Grow).with_limit(Some(5)));
+    let err = interpreter
+        .run_with(&b""[..], Vec::new())
+        .expect_err("tape grew past the limit");
+
+    let state = interpreter.state();
+    assert_eq!(state.tape, [1, 1, 0, 0, 0]);
+    assert_eq!(state.start, -1);
+    assert_eq!(state.memptr, 0);
+    assert_eq!(state.steps, 2);
+    assert_eq!(state.exit, Some(ExitReason::Error(err.to_string())));
+}
+
+#[test]
+fn step() {
+    let mut interpreter = interpreter(b"+.>,.");
+
+    assert_eq!(interpreter.step().unwrap(), StepResult::Continued);
+    assert_eq!(interpreter.step().unwrap(), StepResult::Output(1));
+
+    // nothing runs until there's input
+    assert_eq!(interpreter.step().unwrap(), StepResult::NeedsInput);
+    assert_eq!(interpreter.step().unwrap(), StepResult::NeedsInput);
+    interpreter.input(Some(b'x')).unwrap();
+    assert!(interpreter.input(Some(b'y')).is_err());
+
+    assert_eq!(interpreter.step().unwrap(), StepResult::Output(b'x' as u32));
+    assert_eq!(interpreter.step().unwrap(), StepResult::Continued);
+    assert_eq!(interpreter.step().unwrap(), StepResult::Halted);
+    assert_eq!(interpreter.tape()[1], b'x');
+}
+
+#[test]
+fn run_until() {
+    let mut interpreter = interpreter(b"++++[>+.<-]");
+    let mut io = Io::new(&b""[..], Vec::new());
+
+    let stopped = interpreter
+        .run_until(&mut io, |interpreter| interpreter.tape()[1] == 2)
+        .unwrap();
+    assert!(stopped);
+    io.flush().unwrap();
+    assert_eq!(io.output_mut(), &[1]);
+
+    let stopped = interpreter.run_until(&mut io, |_| false).unwrap();
+    assert!(!stopped);
+    assert_eq!(io.into_output().unwrap(), [1, 2, 3, 4]);
+}
+
+#[test]
+fn limits() {
+    let limits = Limits {
+        fuel: Some(10),
+        ..Limits::default()
+    };
+    let mut stopped = interpreter(b"+[>+<]").with_limits(limits);
+    let state = stopped.run_with(&b""[..], Vec::new()).unwrap();
+    assert_eq!(state.steps, 10);
+    assert_eq!(state.exit, Some(ExitReason::LimitExceeded(Limit::Fuel)));
+    assert_eq!(
+        stopped.step().unwrap(),
+        StepResult::LimitExceeded(Limit::Fuel)
+    );
+
+    // every cell moved over uses up fuel, or a wrapping tape without a zero never stops
+    let state = interpreter(b"+>+>+>+<<<[>]")
+        .with_tape(Tape::new(4, TapePolicy::Wrap))
+        .with_limits(limits)
+        .run_with(&b""[..], Vec::new())
+        .unwrap();
+    assert_eq!(state.steps, 4);
+    assert_eq!(state.exit, Some(ExitReason::LimitExceeded(Limit::Fuel)));
+
+    // the output so far is kept, but nothing past the limit
+    let limits = Limits {
+        output: Some(3),
+        ..Limits::default()
+    };
+    let mut output = Vec::new();
+    let state = interpreter(b"+[.+]")
+        .with_limits(limits)
+        .run_with(&b""[..], &mut output)
+        .unwrap();
+    assert_eq!(output, [1, 2, 3]);
+    assert_eq!(state.exit, Some(ExitReason::LimitExceeded(Limit::Output)));
+
+    let limits = Limits {
+        timeout: Some(Duration::from_millis(10)),
+        ..Limits::default()
+    };
+    let state = interpreter(b"+[]")
+        .with_limits(limits)
+        .run_with(&b""[..], Vec::new())
+        .unwrap();
+    assert_eq!(state.exit, Some(ExitReason::LimitExceeded(Limit::Timeout)));
+}
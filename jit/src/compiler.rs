@@ -1,88 +1,225 @@
+use std::{
+    mem::offset_of,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+    },
+    time::Instant,
+};
+
 use color_eyre::{eyre::Context as _, Result};
 
-use dynasmrt::{dynasm, mmap::MutableBuffer, DynasmApi as _, DynasmLabelApi as _};
-use parser::Instruction;
+use dynasmrt::{dynasm, mmap::MutableBuffer, x64::Assembler, DynasmApi as _, DynasmLabelApi as _};
+use interpreter::{ExitReason, Limit, Limits, MachineState, Prefix, Stdio};
+use parser::{CellWidth, EofPolicy, Instruction, OutputMode, TapePolicy};
 
 #[derive(Debug, Clone)]
-pub struct Compiler<const N: i32> {
+pub struct Compiler {
     instructions: Vec<Instruction>,
+    output: Vec<u8>,
+    memory: Vec<u32>,
+    pointer: usize,
+    steps: u64,
+    width: CellWidth,
+    eof: EofPolicy,
+    output_mode: OutputMode,
+    tape: TapePolicy,
+    limits: Limits,
+    deadline: Option<Instant>,
+}
+
+/// What the generated code runs on, `r15` points to it.
+#[repr(C)]
+struct Machine {
+    /// Cells as little-endian bytes.
+    memory: Vec<u8>,
+    /// Only written when the code returns, same as `steps`.
+    pointer: u64,
+    steps: u64,
+    /// Bytes written so far, not counting the ones the prefix printed.
+    written: u64,
+    max_output: u64,
+    /// Step count the program runs out of fuel at, one lower for every cell a scan moves over.
+    fuel: u64,
+}
+
+/// Where the tape is after it grew, returned in `rax` and `rdx`.
+#[repr(C)]
+struct Tape {
+    memory: *mut u8,
+    length: usize,
 }
 
-impl<const N: i32> Compiler<N> {
-    pub fn new(instructions: Vec<Instruction>) -> Self {
-        Self { instructions }
+impl Compiler {
+    /// Compiles what's left of the program, starting from where `prefix` stopped.
+    pub fn new(prefix: Prefix, width: CellWidth) -> Self {
+        Self {
+            instructions: prefix.rest,
+            output: prefix.output,
+            memory: prefix.memory,
+            pointer: prefix.pointer,
+            steps: prefix.steps,
+            width,
+            eof: EofPolicy::default(),
+            output_mode: OutputMode::default(),
+            tape: TapePolicy::default(),
+            limits: Limits::default(),
+            deadline: None,
+        }
+    }
+
+    pub fn with_eof(mut self, eof: EofPolicy) -> Self {
+        self.eof = eof;
+
+        self
+    }
+
+    pub fn with_output(mut self, output: OutputMode) -> Self {
+        self.output_mode = output;
+
+        self
+    }
+
+    /// Sets what happens when the pointer leaves the tape, which starts out as long as the one
+    /// the prefix left.
+    pub fn with_tape_policy(mut self, policy: TapePolicy) -> Self {
+        self.tape = policy;
+
+        self
     }
 
-    pub fn run(self) -> Result<()> {
+    /// Stops the program once it reaches one of `limits`, which are what the prefix left of them.
+    /// The timeout counts from here. Fuel and time are only looked at when a loop goes around
+    /// again.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self.deadline = limits
+            .timeout
+            .and_then(|timeout| Instant::now().checked_add(timeout));
+
+        self
+    }
+
+    /// Runs the program and returns what it left, even if it stopped with an error.
+    pub fn run(self) -> Result<MachineState> {
+        // everything printed at compile time goes out first
+        let mut io = Stdio::stdio();
+        io.write(&self.output)
+            .wrap_err("failed to write to stdout")?;
+
         let mut ops = dynasmrt::x64::Assembler::new().wrap_err("failed to allocate memory")?;
 
+        let pointer_offset = offset_of!(Machine, pointer) as i32;
+        let steps_offset = offset_of!(Machine, steps) as i32;
+        let fuel_offset = offset_of!(Machine, fuel) as i32;
+
         // r12 will be the address of `memory`
         // r13 will be the value of `pointer`
-        // r12 is got from argument 1 in `rdi`
-        // r13 is set to 0
+        // r14 will be the number of cells in `memory`, only kept up to date when growing
+        // r15 will be the address of the `Machine`
+        // they are got from arguments 1 to 4 in `rdi`, `rsi`, `rdx` and `rcx`
+        // rbx will be the number of instructions run, starting from `Machine::steps`
         dynasm! { ops
             ; .arch x64
             ; push rbp
             ; mov rbp, rsp
+            ; push rbx
             ; push r12
             ; push r13
+            ; push r14
+            ; push r15
+            // keeps the stack aligned for calls
+            ; sub rsp, 8
             ; mov r12, rdi
-            ; xor r13, r13
+            ; mov r13, rsi
+            ; mov r14, rdx
+            ; mov r15, rcx
+            ; mov rbx, QWORD [r15 + steps_offset]
         };
 
         use Instruction::*;
 
+        let width = self.width;
+        let tape = self.tape;
+        let length = self.memory.len() as i32;
+        // read from the generated code, have to outlive it
+        let eof = self.eof;
+        let output_mode = self.output_mode;
+        let io_ptr = &mut io as *mut Stdio as i64;
+        // set by a watchdog thread once the time is up
+        let timed_out = AtomicBool::new(false);
+        let timed_out_ptr = &timed_out as *const AtomicBool as i64;
+        let fuel = self.limits.fuel;
+        let timeout = self.deadline.is_some();
         let mut bracket_stack = Vec::new();
+        // instructions run since `rbx` was last added to
+        let mut uncounted = 0;
         for instruction in self.instructions {
+            // anything that can stop the program has to see the count up to itself
+            let fallible = match instruction {
+                In(_) | Out(_) => true,
+                JumpIfZero(_) | JumpIfNotZero(_) => false,
+                MoveUntilZero(_) if fuel.is_some() || timeout => true,
+                _ => tape != TapePolicy::Wrap,
+            };
+            if fallible {
+                Self::count(&mut ops, &mut uncounted);
+            }
+            uncounted += 1;
+
             match instruction {
-                Add(n) => dynasm! { ops
-                    ; .arch x64
-                    ; add BYTE [r12 + r13], BYTE n as i8
-                },
+                Add(offset, n) => {
+                    Self::index(&mut ops, tape, length, offset as i32);
+                    Self::add(&mut ops, width, n);
+                }
                 Move(n) => {
-                    let n = n as i32;
+                    Self::index(&mut ops, tape, length, n as i32);
 
-                    if n > 0 {
-                        dynasm! { ops
-                            ; .arch x64
-                            ; lea eax, [r13 + n]
-                            ; add r13, (-N - n)
-                            ; cmp eax, N
-                            ; cmovl r13d, eax
-                        }
-                    } else {
-                        dynasm! { ops
-                            ; .arch x64
-                            ; lea eax, [r13 + n]
-                            ; add r13d, N + n
-                            ; test eax, eax
-                            ; cmovns r13d, eax
-                        }
+                    dynasm! { ops
+                        ; .arch x64
+                        ; mov r13d, eax
+                    }
+                }
+                In(offset) => {
+                    Self::index(&mut ops, tape, length, offset as i32);
+                    Self::address(&mut ops, width);
+
+                    dynasm! { ops
+                        ; .arch x64
+                        ; mov esi, width.bytes() as i32
+                        ; mov rdx, QWORD &eof as *const EofPolicy as i64
+                        ; mov rcx, QWORD io_ptr
+                        ; mov rax, QWORD Self::read as *const () as i64
+                        ; call rax
+                        ; test rax,rax
+                        ; jne ->exit
+                    }
+                }
+                Out(offset) => {
+                    Self::index(&mut ops, tape, length, offset as i32);
+                    Self::load(&mut ops, width);
+
+                    dynasm! { ops
+                        ; .arch x64
+                        ; mov edi, ecx
+                        ; mov rsi, QWORD &output_mode as *const OutputMode as i64
+                        ; mov rdx, QWORD io_ptr
+                        ; mov rcx, r15
+                        ; mov rax, QWORD Self::write as *const () as i64
+                        ; call rax
+                        ; test rax,rax
+                        ; jne ->exit
                     }
                 }
-                In => dynasm! { ops
-                    ; .arch x64
-                    ; mov rax, QWORD Self::read as *const () as i64
-                    ; lea rdi, [r12 + r13]
-                    ; call rax
-                    ; test rax,rax
-                    ; jne ->exit
-                },
-                Out => dynasm! { ops
-                    ; .arch x64
-                    ; mov rax, QWORD Self::write as *const () as i64
-                    ; mov rdi, [r12 + r13]
-                    ; call rax
-                    ; test rax,rax
-                    ; jne ->exit
-                },
                 JumpIfZero(_) => {
                     let start_label = ops.new_dynamic_label();
                     let end_label = ops.new_dynamic_label();
 
+                    Self::count(&mut ops, &mut uncounted);
+                    Self::compare_current(&mut ops, width);
+
                     dynasm! { ops
                         ; .arch x64
-                        ; cmp BYTE [r12+r13], 0
                         ; je =>end_label
                         ; =>start_label
                     };
@@ -91,79 +228,106 @@ impl<const N: i32> Compiler<N> {
                 }
                 JumpIfNotZero(_) => {
                     let (start_label, end_label) = bracket_stack.pop().unwrap(); // will never fail
+                    Self::count(&mut ops, &mut uncounted);
+                    Self::compare_current(&mut ops, width);
+
+                    if fuel.is_none() && !timeout {
+                        dynasm! { ops
+                            ; .arch x64
+                            ; jne =>start_label
+                            ; =>end_label
+                        };
+
+                        continue;
+                    }
+
+                    // limits are only looked at when the loop goes around again
+                    dynasm! { ops
+                        ; .arch x64
+                        ; je =>end_label
+                    };
+
+                    if fuel.is_some() {
+                        dynasm! { ops
+                            ; .arch x64
+                            ; cmp rbx, QWORD [r15 + fuel_offset]
+                            ; jae ->out_of_fuel
+                        };
+                    }
+
+                    if timeout {
+                        Self::check_time(&mut ops, timed_out_ptr);
+                    }
+
                     dynasm! { ops
                         ; .arch x64
-                        ; cmp BYTE [r12+r13], 0
-                        ; jne =>start_label
+                        ; jmp =>start_label
                         ; =>end_label
                     };
                 }
-                Clear => dynasm! { ops
-                    ; .arch x64
-                    ; mov BYTE [r12+r13], 0
-                },
-                Multiply(offset, by) => {
-                    let n = offset as i32;
+                Clear(offset) => {
+                    Self::index(&mut ops, tape, length, offset as i32);
+                    Self::store(&mut ops, width, 0);
+                }
+                Set(offset, n) => {
+                    Self::index(&mut ops, tape, length, offset as i32);
+                    Self::store(&mut ops, width, n);
+                }
+                Multiply(from, to, by) => {
+                    Self::index(&mut ops, tape, length, from as i32);
+                    Self::load(&mut ops, width);
 
+                    // the loop this came from never ran, so it didn't get to the target either
                     dynasm! { ops
                         ; .arch x64
-                        ;;
-                        if n > 0 {
-                            dynasm! { ops
-                                ; lea ecx, [r13 + n]
-                                ; lea eax, [r13 + n - N]
-                                ; cmp ecx, N
-                                ; cmovl eax, ecx
-                            }
-                        } else {
-                            dynasm! { ops
-                                ; lea ecx, [r13 + n]
-                                ; lea eax, [r13 + N + n]
-                                ; test ecx, ecx
-                                ; cmovns eax, ecx
-                            }
-                        }
-                        ;;
-                        if by == 1 {
-                            dynasm! { ops
-                                ; mov cl, [r12 + r13]
-                            }
-                        } else {
-                            dynasm! { ops
-                                ; movzx ecx, [r12 + r13]
-                                ; imul ecx, ecx, by as i8 as i32
-                            }
+                        ; test ecx, ecx
+                        ; jz >skip
+                    }
+
+                    if by != 1 {
+                        dynasm! { ops
+                            ; .arch x64
+                            ; imul ecx, ecx, by
                         }
-                        ; add BYTE [r12 + rax], cl
-                        ; mov BYTE [r12 + r13], 0
+                    }
+
+                    Self::index(&mut ops, tape, length, to as i32);
+                    Self::add_ecx(&mut ops, width);
+
+                    dynasm! { ops
+                        ; .arch x64
+                        ; skip:
                     }
                 }
                 MoveUntilZero(n) => {
-                    let n = n as i32;
-
                     dynasm! { ops
                         ; .arch x64
                         ; repeat:
-                        ; cmp BYTE [r12 + r13], 0
-                        ; je >exit
                         ;;
-                        if n > 0 {
-                            dynasm! { ops
-                                ; .arch x64
-                                ; lea eax, [r13 + n]
-                                ; add r13, (-N - n)
-                                ; cmp eax, N
-                                ; cmovl r13d, eax
-                            }
-                        } else {
-                            dynasm! { ops
-                                ; .arch x64
-                                ; lea eax, [r13 + n]
-                                ; add r13d, N + n
-                                ; test eax, eax
-                                ; cmovns r13d, eax
-                            }
-                        }
+                        Self::compare_current(&mut ops, width)
+                        ; je >exit
+                    }
+
+                    // a wrapping tape without a zero cell never stops. `rbx` has the steps run
+                    // before the scan, the scan itself needs one more
+                    if fuel.is_some() {
+                        dynasm! { ops
+                            ; .arch x64
+                            ; lea rax, [rbx + 1]
+                            ; cmp rax, QWORD [r15 + fuel_offset]
+                            ; jae ->out_of_fuel
+                            ; sub QWORD [r15 + fuel_offset], 1
+                        };
+                    }
+                    if timeout {
+                        Self::check_time(&mut ops, timed_out_ptr);
+                    }
+
+                    Self::index(&mut ops, tape, length, n as i32);
+
+                    dynasm! { ops
+                        ; .arch x64
+                        ; mov r13d, eax
                         ; jmp <repeat
                         ; exit:
                     }
@@ -171,14 +335,53 @@ impl<const N: i32> Compiler<N> {
             }
         }
 
+        Self::count(&mut ops, &mut uncounted);
+
         dynasm! { ops
             ; .arch x64
             ; xor rax, rax
             ; ->exit:
+            ; mov QWORD [r15 + pointer_offset], r13
+            ; mov QWORD [r15 + steps_offset], rbx
+            ; add rsp, 8
+            ; pop r15
+            ; pop r14
             ; pop r13
             ; pop r12
+            ; pop rbx
             ; pop rbp
             ; ret
+            // jumped to with the index in `eax`
+            ; ->out_of_bounds:
+            ; movsxd rdi, eax
+            ; mov rax, QWORD Self::out_of_bounds as *const () as i64
+            ; call rax
+            ; jmp ->exit
+            ; ->out_of_fuel:
+            ; mov edi, 0
+            ; jmp >limit
+            ; ->out_of_time:
+            ; mov edi, 1
+            ; limit:
+            ; mov rax, QWORD Self::limit_exceeded as *const () as i64
+            ; call rax
+            ; jmp ->exit
+            // called with the index in `eax`, keeps `rax` and `rcx`
+            ; ->grow:
+            ; push rax
+            ; push rcx
+            ; sub rsp, 8
+            ; mov rdi, r15
+            ; mov esi, eax
+            ; mov edx, width.bytes() as i32
+            ; mov rax, QWORD Self::grow as *const () as i64
+            ; call rax
+            ; mov r12, rax
+            ; mov r14, rdx
+            ; add rsp, 8
+            ; pop rcx
+            ; pop rax
+            ; ret
         };
 
         let code = ops.finalize().unwrap(); // should never fail
@@ -190,38 +393,356 @@ impl<const N: i32> Compiler<N> {
         let buffer = buffer
             .make_exec()
             .wrap_err("failed to make memory executable")?;
-        let mut memory = [0u8; 30_000];
-        unsafe {
-            let code_fn: unsafe extern "sysv64" fn(*mut u8) -> *mut std::io::Error =
-                std::mem::transmute(buffer.as_ptr());
 
-            let error = code_fn(memory.as_mut_ptr());
+        let memory = self
+            .memory
+            .iter()
+            .flat_map(|value| value.to_le_bytes().into_iter().take(width.bytes() as usize))
+            .collect::<Vec<_>>();
+        let length = memory.len() / width.bytes() as usize;
+        let mut machine = Machine {
+            memory,
+            pointer: self.pointer as u64,
+            steps: self.steps,
+            written: 0,
+            max_output: self.limits.output.unwrap_or(u64::MAX),
+            // `steps` starts at the ones the prefix ran
+            fuel: self
+                .limits
+                .fuel
+                .map_or(u64::MAX, |fuel| self.steps.saturating_add(fuel)),
+        };
+        let (finished, done) = mpsc::channel::<()>();
+        let error = std::thread::scope(|scope| {
+            if let Some(deadline) = self.deadline {
+                let timed_out = &timed_out;
+
+                scope.spawn(move || {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+
+                    if let Err(RecvTimeoutError::Timeout) = done.recv_timeout(timeout) {
+                        timed_out.store(true, Ordering::Relaxed);
+                    }
+                });
+            }
+
+            let error = unsafe {
+                let code_fn: unsafe extern "sysv64" fn(
+                    *mut u8,
+                    u64,
+                    usize,
+                    *mut Machine,
+                ) -> *mut std::io::Error = std::mem::transmute(buffer.as_ptr());
+
+                code_fn(
+                    machine.memory.as_mut_ptr(),
+                    machine.pointer,
+                    length,
+                    &mut machine,
+                )
+            };
 
-            if !error.is_null() {
-                return Err(*Box::from_raw(error))?;
+            // wakes up the watchdog, so the scope doesn't wait for the time to be up
+            drop(finished);
+
+            error
+        });
+        let mut exit = if error.is_null() {
+            ExitReason::Halted
+        } else {
+            let error = unsafe { Box::from_raw(error) };
+
+            match error.get_ref().and_then(|err| err.downcast_ref::<Limit>()) {
+                Some(&limit) => ExitReason::LimitExceeded(limit),
+                None => ExitReason::Error(error.to_string()),
             }
+        };
+
+        // output that couldn't be written only matters if the program didn't fail already
+        if let (ExitReason::Halted | ExitReason::LimitExceeded(_), Err(err)) = (&exit, io.flush()) {
+            exit = ExitReason::Error(format!("failed to write to stdout: {err}"));
         }
 
-        Ok(())
+        Ok(MachineState {
+            tape: machine
+                .memory
+                .chunks(width.bytes() as usize)
+                .map(|cell| {
+                    cell.iter()
+                        .rev()
+                        .fold(0, |value, &byte| value << 8 | byte as u32)
+                })
+                .collect(),
+            start: 0,
+            memptr: machine.pointer as isize,
+            steps: machine.steps,
+            exit: Some(exit),
+        })
     }
 
-    extern "sysv64" fn write(value: u8) -> *mut std::io::Error {
-        use std::io::Write;
+    /// Emits code that adds the `uncounted` instructions to `rbx`. Changes flags.
+    fn count(ops: &mut Assembler, uncounted: &mut i32) {
+        if *uncounted != 0 {
+            dynasm! { ops
+                ; .arch x64
+                ; add rbx, *uncounted
+            };
 
-        let mut stdout = std::io::stdout().lock();
-        let result = write!(stdout, "{}", value as char);
+            *uncounted = 0;
+        }
+    }
 
-        match result {
+    /// Emits code that stops the program if the flag at `timed_out` is set. Clobbers `rax`.
+    fn check_time(ops: &mut Assembler, timed_out: i64) {
+        dynasm! { ops
+            ; .arch x64
+            ; mov rax, QWORD timed_out
+            ; cmp BYTE [rax], 0
+            ; jne ->out_of_time
+        };
+    }
+
+    /// Emits code that puts index of the cell at `offset` from the pointer into `rax`, applying
+    /// the tape policy if it is past either end of a tape that is `length` cells long. The
+    /// pointer itself is always on the tape. Clobbers `rdx`.
+    fn index(ops: &mut Assembler, tape: TapePolicy, length: i32, offset: i32) {
+        // going around the tape any number of times leaves a single step over the end
+        let offset = match tape {
+            TapePolicy::Wrap => offset.rem_euclid(length),
+            _ => offset,
+        };
+
+        match tape {
+            _ if offset == 0 => dynasm! { ops
+                ; .arch x64
+                ; mov eax, r13d
+            },
+            TapePolicy::Wrap => dynasm! { ops
+                ; .arch x64
+                ; lea eax, [r13 + offset]
+                ; lea edx, [r13 + offset - length]
+                ; cmp eax, length
+                ; cmovge eax, edx
+            },
+            // negative indices are taken as large unsigned ones
+            TapePolicy::Error => dynasm! { ops
+                ; .arch x64
+                ; lea eax, [r13 + offset]
+                ; cmp eax, length
+                ; jae ->out_of_bounds
+            },
+            TapePolicy::Grow if offset < 0 => dynasm! { ops
+                ; .arch x64
+                ; lea eax, [r13 + offset]
+                ; test eax, eax
+                ; js ->out_of_bounds
+            },
+            TapePolicy::Grow => dynasm! { ops
+                ; .arch x64
+                ; lea eax, [r13 + offset]
+                ; cmp eax, r14d
+                ; jb >inside
+                ; call ->grow
+                ; inside:
+            },
+        }
+    }
+
+    /// Emits `cmp` of the current cell with 0.
+    fn compare_current(ops: &mut Assembler, width: CellWidth) {
+        match width {
+            CellWidth::U8 => dynasm! { ops
+                ; .arch x64
+                ; cmp BYTE [r12 + r13], 0
+            },
+            CellWidth::U16 => dynasm! { ops
+                ; .arch x64
+                ; cmp WORD [r12 + r13 * 2], 0
+            },
+            CellWidth::U32 => dynasm! { ops
+                ; .arch x64
+                ; cmp DWORD [r12 + r13 * 4], 0
+            },
+        }
+    }
+
+    /// Emits code that puts address of the cell at index `rax` into `rdi`.
+    fn address(ops: &mut Assembler, width: CellWidth) {
+        match width {
+            CellWidth::U8 => dynasm! { ops
+                ; .arch x64
+                ; lea rdi, [r12 + rax]
+            },
+            CellWidth::U16 => dynasm! { ops
+                ; .arch x64
+                ; lea rdi, [r12 + rax * 2]
+            },
+            CellWidth::U32 => dynasm! { ops
+                ; .arch x64
+                ; lea rdi, [r12 + rax * 4]
+            },
+        }
+    }
+
+    /// Emits code that adds `n` to the cell at index `rax`.
+    fn add(ops: &mut Assembler, width: CellWidth, n: i32) {
+        match width {
+            CellWidth::U8 => dynasm! { ops
+                ; .arch x64
+                ; add BYTE [r12 + rax], BYTE n as i8
+            },
+            CellWidth::U16 => dynasm! { ops
+                ; .arch x64
+                ; add WORD [r12 + rax * 2], WORD n as i16
+            },
+            CellWidth::U32 => dynasm! { ops
+                ; .arch x64
+                ; add DWORD [r12 + rax * 4], DWORD n
+            },
+        }
+    }
+
+    /// Emits code that sets the cell at index `rax` to `n`.
+    fn store(ops: &mut Assembler, width: CellWidth, n: i32) {
+        match width {
+            CellWidth::U8 => dynasm! { ops
+                ; .arch x64
+                ; mov BYTE [r12 + rax], BYTE n as i8
+            },
+            CellWidth::U16 => dynasm! { ops
+                ; .arch x64
+                ; mov WORD [r12 + rax * 2], WORD n as i16
+            },
+            CellWidth::U32 => dynasm! { ops
+                ; .arch x64
+                ; mov DWORD [r12 + rax * 4], DWORD n
+            },
+        }
+    }
+
+    /// Emits code that zero-extends the cell at index `rax` into `ecx`.
+    fn load(ops: &mut Assembler, width: CellWidth) {
+        match width {
+            CellWidth::U8 => dynasm! { ops
+                ; .arch x64
+                ; movzx ecx, BYTE [r12 + rax]
+            },
+            CellWidth::U16 => dynasm! { ops
+                ; .arch x64
+                ; movzx ecx, WORD [r12 + rax * 2]
+            },
+            CellWidth::U32 => dynasm! { ops
+                ; .arch x64
+                ; mov ecx, DWORD [r12 + rax * 4]
+            },
+        }
+    }
+
+    /// Emits code that adds `ecx` to the cell at index `rax`.
+    fn add_ecx(ops: &mut Assembler, width: CellWidth) {
+        match width {
+            CellWidth::U8 => dynasm! { ops
+                ; .arch x64
+                ; add BYTE [r12 + rax], cl
+            },
+            CellWidth::U16 => dynasm! { ops
+                ; .arch x64
+                ; add WORD [r12 + rax * 2], cx
+            },
+            CellWidth::U32 => dynasm! { ops
+                ; .arch x64
+                ; add DWORD [r12 + rax * 4], ecx
+            },
+        }
+    }
+
+    /// Makes the tape long enough to have a cell at `index`, each cell being `bytes` wide.
+    extern "sysv64" fn grow(machine: &mut Machine, index: u32, bytes: u32) -> Tape {
+        let tape = &mut machine.memory;
+        let cells = (index as usize + 1).max(tape.len() / bytes as usize * 2);
+        tape.resize(cells * bytes as usize, 0);
+
+        Tape {
+            memory: tape.as_mut_ptr(),
+            length: cells,
+        }
+    }
+
+    extern "sysv64" fn out_of_bounds(index: i64) -> *mut std::io::Error {
+        let err = std::io::Error::other(format!("pointer moved out of the tape to cell {index}"));
+
+        Box::into_raw(Box::new(err))
+    }
+
+    /// Reports the limit the program reached, fuel for 0 and time for 1.
+    extern "sysv64" fn limit_exceeded(limit: u32) -> *mut std::io::Error {
+        let limit = match limit {
+            0 => Limit::Fuel,
+            _ => Limit::Timeout,
+        };
+
+        Box::into_raw(Box::new(std::io::Error::other(limit)))
+    }
+
+    /// Writes a cell holding `value`, unless that goes past the output limit.
+    extern "sysv64" fn write(
+        value: u32,
+        output: &OutputMode,
+        io: &mut Stdio,
+        machine: &mut Machine,
+    ) -> *mut std::io::Error {
+        let mut buffer = [0; 4];
+        let bytes = output.encode(value, &mut buffer);
+
+        let written = machine.written + bytes.len() as u64;
+        if written > machine.max_output {
+            return Box::into_raw(Box::new(std::io::Error::other(Limit::Output)));
+        }
+        machine.written = written;
+
+        match io.write(bytes) {
             Err(err) => Box::into_raw(Box::new(err)),
             _ => std::ptr::null_mut(),
         }
     }
 
-    extern "sysv64" fn read(buf: *mut u8) -> *mut std::io::Error {
-        use std::io::Read;
+    /// Reads a byte into the cell at `cell`, which is `bytes` wide.
+    extern "sysv64" fn read(
+        cell: *mut u8,
+        bytes: u32,
+        eof: &EofPolicy,
+        io: &mut Stdio,
+    ) -> *mut std::io::Error {
+        use std::io::ErrorKind;
+
+        let cell = unsafe { std::slice::from_raw_parts_mut(cell, bytes as usize) };
+
+        let result = match io.read() {
+            Ok(Some(byte)) => {
+                cell.fill(0);
+                cell[0] = byte;
+
+                Ok(())
+            }
+            Ok(None) => match eof {
+                EofPolicy::Unchanged => Ok(()),
+                EofPolicy::Zero => {
+                    cell.fill(0);
 
-        let mut stdin = std::io::stdin().lock();
-        let result = stdin.read_exact(unsafe { std::slice::from_raw_parts_mut(buf, 1) });
+                    Ok(())
+                }
+                EofPolicy::MinusOne => {
+                    cell.fill(u8::MAX);
+
+                    Ok(())
+                }
+                EofPolicy::Error => Err(std::io::Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "unexpected end of input",
+                )),
+            },
+            Err(err) => Err(err),
+        };
 
         match result {
             Err(err) => Box::into_raw(Box::new(err)),
@@ -2,8 +2,12 @@ use std::sync::LazyLock;
 
 use clap::Parser as _;
 use cli::Cli;
-use color_eyre::{eyre::Context as _, Result};
-use parser::OptimizationOptions;
+use color_eyre::{
+    eyre::{eyre, Context as _},
+    Result,
+};
+use interpreter::ExitReason;
+use parser::PassManager;
 
 mod cli;
 mod compiler;
@@ -30,12 +34,54 @@ fn main() -> Result<()> {
     };
 
     let parser = parser::Parser::new(&source);
-    let instructions = parser
-        .parse(OptimizationOptions::all())
-        .wrap_err("failed to parse")?;
+    let instructions = match parser.parse_raw() {
+        Ok(instructions) => instructions,
+        Err(err) if ARGS.json_errors => {
+            eprintln!("{}", err.to_json());
+            std::process::exit(1);
+        }
+        Err(err) => return Err(err).wrap_err("failed to parse"),
+    };
+
+    let passes = PassManager::from(ARGS.opt_level);
+    let instructions = passes
+        .run_inspect(instructions, |pass, instructions| {
+            if ARGS.dump_ir {
+                eprintln!("; after {pass}\n{}", parser::dump(instructions));
+            }
+        })
+        .into_iter()
+        .map(|instruction| instruction.value)
+        .collect();
+    let prefix = interpreter::evaluate_prefix(
+        instructions,
+        ARGS.cell_width,
+        ARGS.tape_size as usize,
+        ARGS.tape_policy,
+        ARGS.output_mode,
+        ARGS.limits(),
+        ARGS.eval_budget,
+    )
+    .wrap_err("failed to evaluate program at compile time")?;
 
-    let compiler = compiler::Compiler::<30_000>::new(instructions);
-    compiler.run()?;
+    // the prefix already used up some of the limits
+    let limits = prefix.limits;
+    let compiler = compiler::Compiler::new(prefix, ARGS.cell_width)
+        .with_eof(ARGS.eof)
+        .with_output(ARGS.output_mode)
+        .with_tape_policy(ARGS.tape_policy)
+        .with_limits(limits);
+    let state = compiler.run()?;
 
-    Ok(())
+    if ARGS.dump_state {
+        eprintln!("{}", state.to_json());
+    }
+
+    match state.exit {
+        Some(ExitReason::Error(message)) => Err(eyre!(message)),
+        Some(ExitReason::LimitExceeded(limit)) => {
+            Err(eyre!("stopped after {} steps: {limit}", state.steps))
+        }
+        _ => Ok(()),
+    }
 }
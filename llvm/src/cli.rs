@@ -1,10 +1,88 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
+
+use interpreter::{parse_seconds, Limits};
+use parser::{CellWidth, EofPolicy, OptLevel, OutputMode, TapePolicy, DEFAULT_TAPE_SIZE};
 
 #[derive(Debug, Clone, clap::Parser)]
 #[command(version, about = "Compiles brainfuck into object files/LLVM IR.")]
 pub struct Cli {
     #[arg(help = "Path to file with source code")]
     pub source: Option<PathBuf>,
+    #[arg(long, help = "Print parse errors as JSON")]
+    pub json_errors: bool,
+    #[arg(
+        long,
+        default_value = "3",
+        help = "Optimization level, from 0 (no passes) to 3 (all passes)"
+    )]
+    pub opt_level: OptLevel,
+    #[arg(long, help = "Print IR to stderr after every optimization pass")]
+    pub dump_ir: bool,
+    #[arg(
+        long,
+        requires = "run",
+        help = "Print the tape, pointer, step count and exit reason to stderr as JSON once the program stops"
+    )]
+    pub dump_state: bool,
+    #[arg(
+        long,
+        default_value = "8",
+        help = "Bits per cell (8, 16 or 32), arithmetic wraps around at that width"
+    )]
+    pub cell_width: CellWidth,
+    #[arg(
+        long,
+        default_value = "zero",
+        help = "What ',' does at the end of input: unchanged, zero, minus-one or error"
+    )]
+    pub eof: EofPolicy,
+    #[arg(
+        long,
+        default_value = "bytes",
+        help = "What '.' writes: bytes (the low byte of the cell) or unicode (the cell as a UTF-8 encoded code point)"
+    )]
+    pub output_mode: OutputMode,
+    #[arg(
+        long,
+        default_value_t = DEFAULT_TAPE_SIZE as u32,
+        value_parser = clap::value_parser!(u32).range(1..=i32::MAX as i64),
+        help = "Number of cells on the tape"
+    )]
+    pub tape_size: u32,
+    #[arg(
+        long,
+        default_value = "wrap",
+        help = "What happens when the pointer leaves the tape: wrap, error or grow"
+    )]
+    pub tape_policy: TapePolicy,
+    #[arg(
+        long,
+        default_value_t = 1_000_000,
+        help = "How many instructions to run at compile time, until the program reads input. 0 disables it"
+    )]
+    pub eval_budget: usize,
+    #[arg(
+        long,
+        value_name = "STEPS",
+        requires = "run",
+        help = "Stop once this many optimized instructions ran, cells a scan moves over count too"
+    )]
+    pub fuel: Option<u64>,
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        value_parser = parse_seconds,
+        requires = "run",
+        help = "Stop once the program ran this long"
+    )]
+    pub timeout: Option<Duration>,
+    #[arg(
+        long,
+        value_name = "BYTES",
+        requires = "run",
+        help = "Stop instead of writing more than this many bytes"
+    )]
+    pub max_output: Option<u64>,
     #[arg(short, long, help = "Path to output file")]
     pub output: Option<PathBuf>,
     #[arg(short, long, help = "Target to compile for (e.g. x86_64-pc-linux-gnu)")]
@@ -24,12 +102,20 @@ pub struct Cli {
     pub run: bool,
     #[arg(value_enum, short = 'e', long, default_value = "object")]
     pub emit: Emit,
-    #[arg(short, long, help = "Enable bounds check on >/<. Can be really slow")]
-    pub safe: bool,
-    #[arg(short = 'O', help = "Enable optimization passes")]
+    #[arg(short = 'O', help = "Enable LLVM optimization passes")]
     pub optimize: bool,
 }
 
+impl Cli {
+    pub fn limits(&self) -> Limits {
+        Limits {
+            fuel: self.fuel,
+            timeout: self.timeout,
+            output: self.max_output,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, clap::ValueEnum)]
 pub enum Emit {
     #[default]
@@ -1,3 +1,11 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+    },
+    time::Instant,
+};
+
 use color_eyre::{
     eyre::{eyre, Context as _, ContextCompat},
     Result,
@@ -5,40 +13,139 @@ use color_eyre::{
 use inkwell::{
     attributes::Attribute,
     basic_block::BasicBlock,
+    builder::Builder,
     context::Context,
+    module::Module,
     passes::PassBuilderOptions,
     targets::{CodeModel, RelocMode, Target, TargetMachine, TargetTriple},
-    values::BasicValue as _,
-    OptimizationLevel,
+    values::{BasicValue as _, FunctionValue, IntValue},
+    AddressSpace, OptimizationLevel,
 };
 
-use parser::Instruction;
+use interpreter::{ExitReason, Limit, Limits, MachineState, Prefix};
+use parser::{CellWidth, EofPolicy, Instruction, OutputMode, TapePolicy};
 
 use crate::{cli::Emit, ARGS};
 
 #[derive(Debug, Clone)]
-pub struct Compiler<const N: u64> {
+pub struct Compiler {
     instructions: Vec<Instruction>,
+    output: Vec<u8>,
+    memory: Vec<u32>,
+    pointer: usize,
+    steps: u64,
+    width: CellWidth,
+    eof: EofPolicy,
+    output_mode: OutputMode,
+    tape: TapePolicy,
+    limits: Limits,
+    deadline: Option<Instant>,
 }
 
-impl<const N: u64> Compiler<N> {
-    pub fn new(instructions: Vec<Instruction>) -> Self {
-        Self { instructions }
+/// What `main` leaves behind when the program is run right away, it gets a pointer to it.
+#[repr(C)]
+struct Machine {
+    memory: *const u8,
+    /// Number of cells in `memory`.
+    length: u64,
+    pointer: u64,
+    steps: u64,
+    /// 0 if the program ran to the end, 1 if it ran out of input, 2 if it left the tape and 3, 4
+    /// or 5 if it ran out of fuel, time or output.
+    exit: u64,
+    /// Cell the pointer tried to reach when it left the tape.
+    out_of_bounds: i64,
+    /// Set once the time is up, `main` only reads it.
+    timed_out: *const AtomicBool,
+}
+
+impl Compiler {
+    /// Compiles what's left of the program, starting from where `prefix` stopped.
+    pub fn new(prefix: Prefix, width: CellWidth) -> Self {
+        Self {
+            instructions: prefix.rest,
+            output: prefix.output,
+            memory: prefix.memory,
+            pointer: prefix.pointer,
+            steps: prefix.steps,
+            width,
+            eof: EofPolicy::default(),
+            output_mode: OutputMode::default(),
+            tape: TapePolicy::default(),
+            limits: Limits::default(),
+            deadline: None,
+        }
+    }
+
+    pub fn with_eof(mut self, eof: EofPolicy) -> Self {
+        self.eof = eof;
+
+        self
+    }
+
+    pub fn with_output(mut self, output: OutputMode) -> Self {
+        self.output_mode = output;
+
+        self
+    }
+
+    /// Sets what happens when the pointer leaves the tape, which starts out as long as the one
+    /// the prefix left.
+    pub fn with_tape_policy(mut self, policy: TapePolicy) -> Self {
+        self.tape = policy;
+
+        self
+    }
+
+    /// Stops the program once it reaches one of `limits`, which are what the prefix left of them.
+    /// The timeout counts from here. Fuel and time are only looked at when a loop goes around
+    /// again. Only works with `--run`.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self.deadline = limits
+            .timeout
+            .and_then(|timeout| Instant::now().checked_add(timeout));
+
+        self
     }
 
-    pub fn compile(self) -> Result<()> {
+    /// Compiles the program, or runs it with `--run` and returns what it left.
+    pub fn compile(self) -> Result<Option<MachineState>> {
         let context = Context::create();
         let builder = context.create_builder();
 
         let module = context.create_module("brainfuck");
+        let target_triple = match ARGS.target {
+            Some(ref triple) => TargetTriple::create(triple),
+            None => TargetMachine::get_default_triple(),
+        };
 
         let i8_type = context.i8_type();
+        let cell_type = context.custom_width_int_type(self.width.bits());
+        let i32_type = context.i32_type();
         let i64_type = context.i64_type();
+        let ptr_type = context.ptr_type(AddressSpace::default());
 
-        let fn_type = i64_type.fn_type(&[], false);
+        // only takes the `Machine` to fill in when it's run right away
+        let fn_type = match ARGS.run {
+            true => i64_type.fn_type(&[ptr_type.into()], false),
+            false => i64_type.fn_type(&[], false),
+        };
         let main_fn = module.add_function("main", fn_type, None);
+        let machine_type = context.struct_type(
+            &[
+                ptr_type.into(),
+                i64_type.into(),
+                i64_type.into(),
+                i64_type.into(),
+                i64_type.into(),
+                i64_type.into(),
+                ptr_type.into(),
+            ],
+            false,
+        );
 
-        let fn_type = i8_type.fn_type(&[], false);
+        let fn_type = i32_type.fn_type(&[], false);
         let getchar_fn =
             module.add_function("getchar", fn_type, Some(inkwell::module::Linkage::External));
 
@@ -50,21 +157,147 @@ impl<const N: u64> Compiler<N> {
             context.create_enum_attribute(Attribute::get_named_enum_kind_id("noundef"), 0),
         );
 
+        let put_code_point_fn = match self.output_mode {
+            OutputMode::Bytes => None,
+            OutputMode::Unicode => Some(Self::build_put_code_point(&context, &module, putchar_fn)?),
+        };
+
+        let fn_type = ptr_type.fn_type(&[ptr_type.into(), i32_type.into(), i64_type.into()], false);
+        let memchr_fn =
+            module.add_function("memchr", fn_type, Some(inkwell::module::Linkage::External));
+        // a GNU extension, macOS and Windows don't have it
+        let memrchr_fn = {
+            let triple = target_triple.as_str().to_string_lossy();
+            (triple.contains("linux") || triple.contains("bsd")).then(|| {
+                module.add_function("memrchr", fn_type, Some(inkwell::module::Linkage::External))
+            })
+        };
+
+        let fn_type = i64_type.fn_type(&[i32_type.into(), ptr_type.into(), i64_type.into()], false);
+        let write_fn =
+            module.add_function("write", fn_type, Some(inkwell::module::Linkage::External));
+
+        let fn_type = i32_type.fn_type(&[i32_type.into(), ptr_type.into()], true);
+        let dprintf_fn =
+            module.add_function("dprintf", fn_type, Some(inkwell::module::Linkage::External));
+
+        let fn_type = ptr_type.fn_type(&[i64_type.into(), i64_type.into()], false);
+        let calloc_fn =
+            module.add_function("calloc", fn_type, Some(inkwell::module::Linkage::External));
+
+        let fn_type = ptr_type.fn_type(&[ptr_type.into(), i64_type.into()], false);
+        let realloc_fn =
+            module.add_function("realloc", fn_type, Some(inkwell::module::Linkage::External));
+
         let entry_block = context.append_basic_block(main_fn, "entry");
         builder.position_at_end(entry_block);
 
-        let array_type = i8_type.array_type(N as u32);
-        let memory = builder.build_array_alloca(i8_type, i64_type.const_int(N, false), "memory")?;
+        let tape = self.tape;
+        let bytes = self.width.bytes() as u64;
+        // only changes when the tape grows
+        let length = self.memory.len() as u64;
+
+        let memory = builder.build_alloca(ptr_type, "memory")?;
+        let memory_length = builder.build_alloca(i64_type, "memory_length")?;
         let memptr = builder.build_alloca(i64_type, "memptr")?;
+        // index that left the tape, printed by the `out_of_bounds` block
+        let out_of_bounds_idx = builder.build_alloca(i64_type, "out_of_bounds_idx")?;
+        // instructions run, only brought up to date before anything that can stop the program
+        let steps = builder.build_alloca(i64_type, "steps")?;
+        builder.build_store(steps, i64_type.const_int(self.steps, false))?;
+        // bytes written, not counting the ones the prefix printed
+        let written = builder.build_alloca(i64_type, "written")?;
+        builder.build_store(written, i64_type.const_zero())?;
 
-        // initialize array with 0s
-        builder.build_memset(
-            memory,
-            1,
-            i8_type.const_zero(),
-            i64_type.const_int(N, false),
+        let fuel = self.limits.fuel;
+        let max_output = self.limits.output;
+        // step count the program runs out of fuel at, one lower for every cell a scan moves over.
+        // `steps` starts at the ones the prefix ran
+        let fuel_left = builder.build_alloca(i64_type, "fuel_left")?;
+        builder.build_store(
+            fuel_left,
+            i64_type.const_int(
+                fuel.map_or(u64::MAX, |fuel| self.steps.saturating_add(fuel)),
+                false,
+            ),
         )?;
-        builder.build_store(memptr, i64_type.const_zero())?;
+        // flag the watchdog sets once the time is up, only there when run right away
+        let timed_out = match (self.deadline, main_fn.get_first_param()) {
+            (Some(_), Some(machine)) => {
+                let field = builder.build_struct_gep(
+                    machine_type,
+                    machine.into_pointer_value(),
+                    6,
+                    "timed_out_field",
+                )?;
+
+                Some(
+                    builder
+                        .build_load(ptr_type, field, "timed_out")?
+                        .into_pointer_value(),
+                )
+            }
+            _ => None,
+        };
+
+        let memory_value = builder
+            .build_call(
+                calloc_fn,
+                &[
+                    i64_type.const_int(length, false).into(),
+                    i64_type.const_int(bytes, false).into(),
+                ],
+                "calloc",
+            )?
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_pointer_value();
+        builder.build_store(memory, memory_value)?;
+        builder.build_store(memory_length, i64_type.const_int(length, false))?;
+
+        if self.memory.iter().any(|&value| value != 0) {
+            // start from the tape left by the part that ran at compile time
+            let values = self
+                .memory
+                .iter()
+                .map(|&value| cell_type.const_int(value as u64, false))
+                .collect::<Vec<_>>();
+
+            let initial = module.add_global(cell_type.array_type(length as u32), None, "tape");
+            initial.set_initializer(&cell_type.const_array(&values));
+            initial.set_constant(true);
+            initial.set_linkage(inkwell::module::Linkage::Private);
+
+            builder.build_memcpy(
+                memory_value,
+                1,
+                initial.as_pointer_value(),
+                1,
+                i64_type.const_int(length * bytes, false),
+            )?;
+        }
+        builder.build_store(memptr, i64_type.const_int(self.pointer as u64, false))?;
+
+        if !self.output.is_empty() {
+            // everything printed at compile time goes out with a single write
+            let text = context.const_string(&self.output, false);
+
+            let output = module.add_global(text.get_type(), None, "output");
+            output.set_initializer(&text);
+            output.set_constant(true);
+            output.set_linkage(inkwell::module::Linkage::Private);
+
+            builder.build_call(
+                write_fn,
+                &[
+                    i32_type.const_int(1, false).into(),
+                    output.as_pointer_value().into(),
+                    i64_type.const_int(self.output.len() as u64, false).into(),
+                ],
+                "write",
+            )?;
+        }
 
         #[derive(Debug, Clone)]
         struct LoopBlock<'ctx> {
@@ -73,101 +306,432 @@ impl<const N: u64> Compiler<N> {
             end: BasicBlock<'ctx>,
         }
 
-        let mut loop_stack = Vec::new();
-        for instruction in self.instructions {
-            use Instruction::*;
+        // only jumped to with `TapePolicy::Error` or `TapePolicy::Grow`, prints the error and
+        // returns 1
+        let out_of_bounds = context.append_basic_block(main_fn, "out_of_bounds");
 
-            match instruction {
-                Add(n) => {
-                    let memptr_value = builder
-                        .build_load(i64_type, memptr, "add_idx")?
+        // number of cells on the tape
+        let tape_length = |name: &str| -> Result<_> {
+            Ok(match tape {
+                TapePolicy::Grow => builder
+                    .build_load(i64_type, memory_length, &format!("{name}_length"))?
+                    .into_int_value(),
+                _ => i64_type.const_int(length, false),
+            })
+        };
+
+        // goes to `out_of_bounds` with `idx` if `cmp` holds, carries on in a new block otherwise
+        let check = |cmp, idx, name: &str| -> Result<()> {
+            let inside = context.append_basic_block(main_fn, &format!("{name}_inside"));
+
+            builder.build_store(out_of_bounds_idx, idx)?;
+            builder.build_conditional_branch(cmp, out_of_bounds, inside)?;
+            builder.position_at_end(inside);
+
+            Ok(())
+        };
+
+        // index of the cell `offset` cells away from the one at `idx`, applying the tape policy
+        // if it is past either end. `idx` itself is always on the tape
+        let step = |idx, offset: isize, name: &str| -> Result<_> {
+            let offset = match tape {
+                // going around the tape any number of times leaves a single step over the end
+                TapePolicy::Wrap => offset.rem_euclid(length as isize),
+                _ => offset,
+            };
+
+            if offset == 0 {
+                return Ok(idx);
+            }
+
+            let next = builder.build_int_add(
+                idx,
+                i64_type.const_int(offset as u64, false),
+                &format!("{name}_idx_add"),
+            )?;
+
+            match tape {
+                TapePolicy::Wrap => {
+                    let cmp = builder.build_int_compare(
+                        inkwell::IntPredicate::UGE,
+                        next,
+                        i64_type.const_int(length, false),
+                        &format!("{name}_wrap_cmp"),
+                    )?;
+                    let wrapped = builder.build_int_sub(
+                        next,
+                        i64_type.const_int(length, false),
+                        &format!("{name}_wrapped"),
+                    )?;
+
+                    Ok(builder
+                        .build_select(cmp, wrapped, next, &format!("{name}_wrap"))?
+                        .into_int_value())
+                }
+                // negative indices are taken as large unsigned ones
+                TapePolicy::Error => {
+                    let cmp = builder.build_int_compare(
+                        inkwell::IntPredicate::UGE,
+                        next,
+                        i64_type.const_int(length, false),
+                        &format!("{name}_bounds_cmp"),
+                    )?;
+                    check(cmp, next, name)?;
+
+                    Ok(next)
+                }
+                TapePolicy::Grow if offset < 0 => {
+                    let cmp = builder.build_int_compare(
+                        inkwell::IntPredicate::SLT,
+                        next,
+                        i64_type.const_zero(),
+                        &format!("{name}_bounds_cmp"),
+                    )?;
+                    check(cmp, next, name)?;
+
+                    Ok(next)
+                }
+                TapePolicy::Grow => {
+                    let grow = context.append_basic_block(main_fn, &format!("{name}_grow"));
+                    let inside = context.append_basic_block(main_fn, &format!("{name}_inside"));
+
+                    let old_length = tape_length(name)?;
+                    let cmp = builder.build_int_compare(
+                        inkwell::IntPredicate::UGE,
+                        next,
+                        old_length,
+                        &format!("{name}_bounds_cmp"),
+                    )?;
+                    builder.build_conditional_branch(cmp, grow, inside)?;
+
+                    // at least double the tape, so growing one cell at a time stays cheap
+                    builder.position_at_end(grow);
+                    let needed = builder.build_int_add(
+                        next,
+                        i64_type.const_int(1, false),
+                        &format!("{name}_needed"),
+                    )?;
+                    let doubled = builder.build_int_mul(
+                        old_length,
+                        i64_type.const_int(2, false),
+                        &format!("{name}_doubled"),
+                    )?;
+                    let cmp = builder.build_int_compare(
+                        inkwell::IntPredicate::UGT,
+                        needed,
+                        doubled,
+                        &format!("{name}_grow_cmp"),
+                    )?;
+                    let new_length = builder
+                        .build_select(cmp, needed, doubled, &format!("{name}_new_length"))?
                         .into_int_value();
-                    let elptr = unsafe {
+
+                    let cell_size = i64_type.const_int(bytes, false);
+                    let old_size = builder.build_int_mul(
+                        old_length,
+                        cell_size,
+                        &format!("{name}_old_size"),
+                    )?;
+                    let new_size = builder.build_int_mul(
+                        new_length,
+                        cell_size,
+                        &format!("{name}_new_size"),
+                    )?;
+
+                    let old_memory = builder
+                        .build_load(ptr_type, memory, &format!("{name}_old_memory"))?
+                        .into_pointer_value();
+                    let new_memory = builder
+                        .build_call(
+                            realloc_fn,
+                            &[old_memory.into(), new_size.into()],
+                            &format!("{name}_realloc"),
+                        )?
+                        .try_as_basic_value()
+                        .left()
+                        .unwrap()
+                        .into_pointer_value();
+
+                    // realloc leaves the new part uninitialized
+                    let new_part = unsafe {
                         builder.build_in_bounds_gep(
-                            array_type,
-                            memory,
-                            &[i64_type.const_zero(), memptr_value],
-                            "add_elptr",
+                            i8_type,
+                            new_memory,
+                            &[old_size],
+                            &format!("{name}_new_part"),
                         )?
                     };
+                    let new_part_size = builder.build_int_sub(
+                        new_size,
+                        old_size,
+                        &format!("{name}_new_part_size"),
+                    )?;
+                    builder.build_memset(new_part, 1, i8_type.const_zero(), new_part_size)?;
+
+                    builder.build_store(memory, new_memory)?;
+                    builder.build_store(memory_length, new_length)?;
+                    builder.build_unconditional_branch(inside)?;
+
+                    builder.position_at_end(inside);
+
+                    Ok(next)
+                }
+            }
+        };
+
+        // index of the cell at `offset` from the pointer
+        let index = |offset: isize, name: &str| -> Result<_> {
+            let memptr_value = builder
+                .build_load(i64_type, memptr, &format!("{name}_idx"))?
+                .into_int_value();
+
+            step(memptr_value, offset, name)
+        };
+
+        // pointer to the cell at `idx`, has to be got after the tape had a chance to grow
+        let element = |idx, name: &str| -> Result<_> {
+            let memory_value = builder
+                .build_load(ptr_type, memory, &format!("{name}_memory"))?
+                .into_pointer_value();
+
+            Ok(unsafe {
+                builder.build_in_bounds_gep(
+                    cell_type,
+                    memory_value,
+                    &[idx],
+                    &format!("{name}_elptr"),
+                )?
+            })
+        };
+
+        let cell = |offset: isize, name: &str| -> Result<_> {
+            let idx = index(offset, name)?;
+
+            element(idx, name)
+        };
+
+        // adds the instructions run since the last count to `steps`
+        let count = |uncounted: &mut u64| -> Result<()> {
+            if *uncounted != 0 {
+                let value = builder
+                    .build_load(i64_type, steps, "steps")?
+                    .into_int_value();
+                let value = builder.build_int_add(
+                    value,
+                    i64_type.const_int(*uncounted, false),
+                    "steps_add",
+                )?;
+                builder.build_store(steps, value)?;
+
+                *uncounted = 0;
+            }
+
+            Ok(())
+        };
+
+        // each stops the program at a limit and returns 1, without printing anything
+        let out_of_fuel = context.append_basic_block(main_fn, "out_of_fuel");
+        let out_of_time = context.append_basic_block(main_fn, "out_of_time");
+        let out_of_output = context.append_basic_block(main_fn, "out_of_output");
+
+        // goes to `out_of_time` if the time is up, carries on in a new block otherwise
+        let check_time = |name: &str| -> Result<()> {
+            let Some(timed_out) = timed_out else {
+                return Ok(());
+            };
+
+            let flag = builder.build_load(i8_type, timed_out, &format!("{name}_timed_out"))?;
+            // set from another thread, so it has to be loaded every time
+            flag.as_instruction_value()
+                .unwrap() // a load is always an instruction
+                .set_volatile(true)
+                .map_err(|err| eyre!(err))?;
+            let cmp = builder.build_int_compare(
+                inkwell::IntPredicate::NE,
+                flag.into_int_value(),
+                i8_type.const_zero(),
+                &format!("{name}_time_cmp"),
+            )?;
+
+            let inside = context.append_basic_block(main_fn, &format!("{name}_in_time"));
+            builder.build_conditional_branch(cmp, out_of_time, inside)?;
+            builder.position_at_end(inside);
+
+            Ok(())
+        };
+
+        // fills in the `Machine` right before `main` returns, if it was given one
+        let save_state = |exit: u64| -> Result<()> {
+            let Some(machine) = main_fn.get_first_param() else {
+                return Ok(());
+            };
+
+            let out_of_bounds = match exit {
+                2 => builder.build_load(i64_type, out_of_bounds_idx, "state_out_of_bounds")?,
+                _ => i64_type.const_zero().into(),
+            };
+            let fields = [
+                builder.build_load(ptr_type, memory, "state_memory")?,
+                builder.build_load(i64_type, memory_length, "state_length")?,
+                builder.build_load(i64_type, memptr, "state_memptr")?,
+                builder.build_load(i64_type, steps, "state_steps")?,
+                i64_type.const_int(exit, false).into(),
+                out_of_bounds,
+            ];
+
+            for (idx, value) in fields.into_iter().enumerate() {
+                let field = builder.build_struct_gep(
+                    machine_type,
+                    machine.into_pointer_value(),
+                    idx as u32,
+                    "state_field",
+                )?;
+                builder.build_store(field, value)?;
+            }
+
+            Ok(())
+        };
+
+        let width = self.width;
+        let eof = self.eof;
+        // only jumped to with `EofPolicy::Error`, prints the error and returns 1
+        let eof_error = context.append_basic_block(main_fn, "eof_error");
+        let mut loop_stack = Vec::new();
+        // instructions run since `steps` was last added to
+        let mut uncounted = 0;
+        for instruction in self.instructions {
+            use Instruction::*;
+
+            // anything that can stop the program has to see the count up to itself
+            let fallible = match instruction {
+                In(_) if eof == EofPolicy::Error => true,
+                Out(_) if max_output.is_some() => true,
+                MoveUntilZero(_) if fuel.is_some() || timed_out.is_some() => true,
+                JumpIfZero(_) | JumpIfNotZero(_) => false,
+                _ => tape != TapePolicy::Wrap,
+            };
+            if fallible {
+                count(&mut uncounted)?;
+            }
+            uncounted += 1;
+
+            match instruction {
+                Add(offset, n) => {
+                    let elptr = cell(offset, "add")?;
 
                     let value = builder
-                        .build_load(i8_type, elptr, "add_value")?
+                        .build_load(cell_type, elptr, "add_value")?
                         .into_int_value();
-                    let add =
-                        builder.build_int_add(value, i8_type.const_int(n as u64, false), "add")?;
+                    let add = builder.build_int_add(
+                        value,
+                        cell_type.const_int(n as u64, false),
+                        "add",
+                    )?;
                     builder.build_store(elptr, add)?;
                 }
                 Move(n) => {
-                    let memptr_value = builder
-                        .build_load(i64_type, memptr, "move_idx")?
-                        .into_int_value();
-                    let mut add = builder.build_int_add(
-                        memptr_value,
-                        i64_type.const_int(n as u64, false),
-                        "move_idx_add",
-                    )?;
-
-                    if ARGS.safe {
-                        add = builder.build_int_signed_rem(
-                            add,
-                            i64_type.const_int(N, false),
-                            "move_idx_rem",
-                        )?;
-                    }
+                    let idx = index(n, "move")?;
 
-                    builder.build_store(memptr, add)?;
+                    builder.build_store(memptr, idx)?;
                 }
-                In => {
+                In(offset) => {
+                    // a cell off the tape stops the program before anything is read
+                    let elptr = cell(offset, "in")?;
                     let call = builder.build_call(getchar_fn, &[], "getchar")?;
-                    let char = call.try_as_basic_value().left().unwrap(); // ?
+                    let char = call.try_as_basic_value().left().unwrap().into_int_value(); // ?
 
+                    // getchar returns a byte as an int, or -1 at the end of input
                     let cmp = builder.build_int_compare(
                         inkwell::IntPredicate::EQ,
-                        char.into_int_value(),
-                        i8_type.const_int(u64::MAX, false),
+                        char,
+                        i32_type.const_all_ones(),
                         "in_eof_cmp",
                     )?;
+                    let value =
+                        builder.build_int_truncate_or_bit_cast(char, cell_type, "in_value")?;
 
-                    let char = builder.build_select(
-                        cmp,
-                        i8_type.const_int(0, false).as_basic_value_enum(),
-                        char,
-                        "in_eof_zero",
-                    )?;
+                    let value = match eof {
+                        EofPolicy::Unchanged => {
+                            let old = builder.build_load(cell_type, elptr, "in_old_value")?;
 
-                    let memptr_value = builder
-                        .build_load(i64_type, memptr, "in_idx")?
-                        .into_int_value();
-                    let elptr = unsafe {
-                        builder.build_in_bounds_gep(
-                            array_type,
-                            memory,
-                            &[i64_type.const_zero(), memptr_value],
-                            "in_elptr",
-                        )?
+                            builder.build_select(cmp, old, value.into(), "in_eof_unchanged")?
+                        }
+                        EofPolicy::Zero => builder.build_select(
+                            cmp,
+                            cell_type.const_zero(),
+                            value,
+                            "in_eof_zero",
+                        )?,
+                        EofPolicy::MinusOne => builder.build_select(
+                            cmp,
+                            cell_type.const_all_ones(),
+                            value,
+                            "in_eof_minus_one",
+                        )?,
+                        EofPolicy::Error => {
+                            let in_read = context.append_basic_block(main_fn, "in_read");
+
+                            builder.build_conditional_branch(cmp, eof_error, in_read)?;
+                            builder.position_at_end(in_read);
+
+                            value.into()
+                        }
                     };
 
-                    builder.build_store(elptr, char)?;
+                    builder.build_store(elptr, value)?;
                 }
-                Out => {
-                    let memptr_value = builder
-                        .build_load(i64_type, memptr, "out_idx")?
-                        .into_int_value();
-                    let elptr = unsafe {
-                        builder.build_in_bounds_gep(
-                            array_type,
-                            memory,
-                            &[i64_type.const_zero(), memptr_value],
-                            "out_elptr",
-                        )?
-                    };
+                Out(offset) => {
+                    let elptr = cell(offset, "out")?;
 
                     let value = builder
-                        .build_load(i8_type, elptr, "out_value")?
+                        .build_load(cell_type, elptr, "out_value")?
                         .into_int_value();
 
-                    builder.build_call(putchar_fn, &[value.into()], "putchar")?;
+                    if let Some(max_output) = max_output {
+                        let bytes = match put_code_point_fn {
+                            Some(_) => Self::build_encoded_length(&context, &builder, value)?,
+                            None => i64_type.const_int(1, false),
+                        };
+                        let written_value = builder
+                            .build_load(i64_type, written, "out_written")?
+                            .into_int_value();
+                        let written_value =
+                            builder.build_int_add(written_value, bytes, "out_written_add")?;
+                        let cmp = builder.build_int_compare(
+                            inkwell::IntPredicate::UGT,
+                            written_value,
+                            i64_type.const_int(max_output, false),
+                            "out_limit_cmp",
+                        )?;
+
+                        let out_write = context.append_basic_block(main_fn, "out_write");
+                        builder.build_conditional_branch(cmp, out_of_output, out_write)?;
+                        builder.position_at_end(out_write);
+                        builder.build_store(written, written_value)?;
+                    }
+
+                    match put_code_point_fn {
+                        Some(put_code_point_fn) => {
+                            let value = builder.build_int_z_extend_or_bit_cast(
+                                value,
+                                i32_type,
+                                "out_code_point",
+                            )?;
+
+                            builder.build_call(
+                                put_code_point_fn,
+                                &[value.into()],
+                                "put_code_point",
+                            )?;
+                        }
+                        None => {
+                            // only the low byte is printed
+                            let value = builder
+                                .build_int_truncate_or_bit_cast(value, i8_type, "out_byte")?;
+
+                            builder.build_call(putchar_fn, &[value.into()], "putchar")?;
+                        }
+                    }
                 }
                 JumpIfZero(_) => {
                     let loop_block = LoopBlock {
@@ -178,28 +742,19 @@ impl<const N: u64> Compiler<N> {
 
                     loop_stack.push(loop_block.clone());
 
+                    count(&mut uncounted)?;
                     builder.build_unconditional_branch(loop_block.start)?;
                     builder.position_at_end(loop_block.start);
 
-                    let memptr_value = builder
-                        .build_load(i64_type, memptr, "loop_start_idx")?
-                        .into_int_value();
-                    let elptr = unsafe {
-                        builder.build_in_bounds_gep(
-                            array_type,
-                            memory,
-                            &[i64_type.const_zero(), memptr_value],
-                            "loop_start_elptr",
-                        )?
-                    };
+                    let elptr = cell(0, "loop_start")?;
 
                     let value = builder
-                        .build_load(i8_type, elptr, "loop_start_value")?
+                        .build_load(cell_type, elptr, "loop_start_value")?
                         .into_int_value();
                     let cmp = builder.build_int_compare(
                         inkwell::IntPredicate::NE,
                         value,
-                        i8_type.const_zero(),
+                        cell_type.const_zero(),
                         "loop_start_cmp",
                     )?;
 
@@ -208,92 +763,347 @@ impl<const N: u64> Compiler<N> {
                 }
                 JumpIfNotZero(_) => {
                     let loop_block = loop_stack.pop().unwrap(); // will never fail
+                    count(&mut uncounted)?;
+
+                    // limits are only looked at when the loop goes around again
+                    if fuel.is_some() || timed_out.is_some() {
+                        let elptr = cell(0, "loop_again")?;
+                        let value = builder
+                            .build_load(cell_type, elptr, "loop_again_value")?
+                            .into_int_value();
+                        let cmp = builder.build_int_compare(
+                            inkwell::IntPredicate::NE,
+                            value,
+                            cell_type.const_zero(),
+                            "loop_again_cmp",
+                        )?;
+
+                        let loop_again = context.append_basic_block(main_fn, "loop_again");
+                        builder.build_conditional_branch(cmp, loop_again, loop_block.end)?;
+                        builder.position_at_end(loop_again);
+
+                        if fuel.is_some() {
+                            let steps_value = builder
+                                .build_load(i64_type, steps, "loop_steps")?
+                                .into_int_value();
+                            let fuel_value = builder
+                                .build_load(i64_type, fuel_left, "loop_fuel")?
+                                .into_int_value();
+                            let cmp = builder.build_int_compare(
+                                inkwell::IntPredicate::UGE,
+                                steps_value,
+                                fuel_value,
+                                "loop_fuel_cmp",
+                            )?;
+
+                            let fueled = context.append_basic_block(main_fn, "loop_fueled");
+                            builder.build_conditional_branch(cmp, out_of_fuel, fueled)?;
+                            builder.position_at_end(fueled);
+                        }
+
+                        check_time("loop")?;
+                    }
+
                     builder.build_unconditional_branch(loop_block.start)?;
                     builder.position_at_end(loop_block.end);
                 }
-                Clear => {
-                    let memptr_value = builder
-                        .build_load(i64_type, memptr, "clear_idx")?
-                        .into_int_value();
-                    let elptr = unsafe {
-                        builder.build_in_bounds_gep(
-                            array_type,
-                            memory,
-                            &[i64_type.const_zero(), memptr_value],
-                            "clear_elptr",
-                        )?
-                    };
+                Clear(offset) => {
+                    let elptr = cell(offset, "clear")?;
 
-                    builder.build_store(elptr, i8_type.const_zero())?;
+                    builder.build_store(elptr, cell_type.const_zero())?;
                 }
-                Multiply(offset, by) => {
-                    let memptr_value = builder
-                        .build_load(i64_type, memptr, "multiply_idx")?
+                Set(offset, n) => {
+                    let elptr = cell(offset, "set")?;
+
+                    builder.build_store(elptr, cell_type.const_int(n as u64, false))?;
+                }
+                Multiply(from, to, by) => {
+                    let elptr_from = cell(from, "multiply_from")?;
+                    let value_from = builder
+                        .build_load(cell_type, elptr_from, "multiply_value_from")?
                         .into_int_value();
 
-                    let mut add = builder.build_int_add(
-                        memptr_value,
-                        i64_type.const_int(offset as u64, false),
-                        "multiply_idx_add",
-                    )?;
+                    // the loop this came from never ran, so it didn't get to the target either.
+                    // wrapping can't fail, so then there's no need to branch
+                    let skip = if tape != TapePolicy::Wrap {
+                        let multiply = context.append_basic_block(main_fn, "multiply");
+                        let skip = context.append_basic_block(main_fn, "multiply_skip");
 
-                    if ARGS.safe {
-                        add = builder.build_int_signed_rem(
-                            add,
-                            i64_type.const_int(N, false),
-                            "multiply_idx_rem",
+                        let cmp = builder.build_int_compare(
+                            inkwell::IntPredicate::NE,
+                            value_from,
+                            cell_type.const_zero(),
+                            "multiply_cmp",
                         )?;
-                    }
+                        builder.build_conditional_branch(cmp, multiply, skip)?;
+                        builder.position_at_end(multiply);
 
-                    let (elptr_current, elptr_to) = unsafe {
-                        (
-                            builder.build_in_bounds_gep(
-                                array_type,
-                                memory,
-                                &[i64_type.const_zero(), memptr_value],
-                                "multiply_elptr_current",
-                            )?,
-                            builder.build_in_bounds_gep(
-                                array_type,
-                                memory,
-                                &[i64_type.const_zero(), add],
-                                "multiply_elptr_to",
-                            )?,
-                        )
+                        Some(skip)
+                    } else {
+                        None
                     };
 
-                    let value_current = builder
-                        .build_load(i8_type, elptr_current, "multiply_value_current")?
-                        .into_int_value();
+                    let elptr_to = cell(to, "multiply_to")?;
                     let value_to = builder
-                        .build_load(i8_type, elptr_to, "multiply_value_to")?
+                        .build_load(cell_type, elptr_to, "multiply_value_to")?
                         .into_int_value();
 
                     let mul = if by != 1 {
                         builder.build_int_mul(
-                            value_current,
-                            i8_type.const_int(by as u64, false),
+                            value_from,
+                            cell_type.const_int(by as u64, false),
                             "multiply_mul",
                         )?
                     } else {
-                        value_current
+                        value_from
                     };
                     let add = builder.build_int_add(value_to, mul, "multiply_add")?;
                     builder.build_store(elptr_to, add)?;
-                    builder.build_store(elptr_current, i8_type.const_zero())?;
+
+                    if let Some(skip) = skip {
+                        builder.build_unconditional_branch(skip)?;
+                        builder.position_at_end(skip);
+                    }
+                }
+                MoveUntilZero(n) => {
+                    let scan_start = context.append_basic_block(main_fn, "scan_start");
+                    let scan_body = context.append_basic_block(main_fn, "scan_body");
+                    let scan_exit = context.append_basic_block(main_fn, "scan_exit");
+                    let scan_end = context.append_basic_block(main_fn, "scan_end");
+
+                    let memptr_value = builder
+                        .build_load(i64_type, memptr, "scan_idx")?
+                        .into_int_value();
+
+                    // `[>]` and `[<]` are by far the most common scans, so let libc find the zero
+                    // byte for us. that only works for 8-bit cells, a wider zero cell isn't just
+                    // any zero byte and libc has nothing to look for one. if there is no zero
+                    // before the end of the tape, fall back to the loop below, starting at the
+                    // last cell it looked at, so it leaves the tape the same way `Move` does
+                    let search_fn = match n {
+                        1 => Some(memchr_fn),
+                        -1 => memrchr_fn,
+                        _ => None,
+                    };
+                    let (scan_from, scan_pred) =
+                        if let Some(search_fn) = search_fn.filter(|_| width == CellWidth::U8) {
+                            let scan_found = context.append_basic_block(main_fn, "scan_found");
+
+                            let memory_value = builder
+                                .build_load(ptr_type, memory, "scan_memory")?
+                                .into_pointer_value();
+                            // forwards it looks from the current cell to the end, backwards from the
+                            // start up to the current cell
+                            let (from, len, last) = if n == 1 {
+                                let elptr = unsafe {
+                                    builder.build_in_bounds_gep(
+                                        cell_type,
+                                        memory_value,
+                                        &[memptr_value],
+                                        "scan_elptr",
+                                    )?
+                                };
+                                let scan_length = tape_length("scan")?;
+                                let len =
+                                    builder.build_int_sub(scan_length, memptr_value, "scan_len")?;
+                                let last = builder.build_int_sub(
+                                    scan_length,
+                                    i64_type.const_int(1, false),
+                                    "scan_last",
+                                )?;
+                                (elptr, len, last)
+                            } else {
+                                let len = builder.build_int_add(
+                                    memptr_value,
+                                    i64_type.const_int(1, false),
+                                    "scan_len",
+                                )?;
+                                (memory_value, len, i64_type.const_zero())
+                            };
+
+                            let found = builder
+                                .build_call(
+                                    search_fn,
+                                    &[from.into(), i32_type.const_zero().into(), len.into()],
+                                    "scan_search",
+                                )?
+                                .try_as_basic_value()
+                                .left()
+                                .unwrap()
+                                .into_pointer_value();
+                            let not_found = builder.build_is_null(found, "scan_not_found")?;
+                            let found_idx = builder.build_ptr_diff(
+                                i8_type,
+                                found,
+                                memory_value,
+                                "scan_found_idx",
+                            )?;
+
+                            // the cells libc went over use up fuel as well, all at once, so this can
+                            // go past it
+                            if fuel.is_some() {
+                                let to = builder
+                                    .build_select(not_found, last, found_idx, "scan_to")?
+                                    .into_int_value();
+                                let moves = if n == 1 {
+                                    builder.build_int_sub(to, memptr_value, "scan_moves")?
+                                } else {
+                                    builder.build_int_sub(memptr_value, to, "scan_moves")?
+                                };
+                                let fuel_value = builder
+                                    .build_load(i64_type, fuel_left, "scan_fuel")?
+                                    .into_int_value();
+                                let over = builder.build_int_compare(
+                                    inkwell::IntPredicate::UGT,
+                                    moves,
+                                    fuel_value,
+                                    "scan_fuel_over",
+                                )?;
+                                let fuel_value =
+                                    builder.build_int_sub(fuel_value, moves, "scan_fuel_sub")?;
+                                let fuel_value = builder.build_select(
+                                    over,
+                                    i64_type.const_zero(),
+                                    fuel_value,
+                                    "scan_fuel_left",
+                                )?;
+                                builder.build_store(fuel_left, fuel_value)?;
+                            }
+
+                            let scan_pred = builder.get_insert_block().unwrap(); // always positioned
+                            builder.build_conditional_branch(not_found, scan_start, scan_found)?;
+
+                            builder.position_at_end(scan_found);
+                            builder.build_store(memptr, found_idx)?;
+                            builder.build_unconditional_branch(scan_end)?;
+
+                            (last, scan_pred)
+                        } else {
+                            let scan_pred = builder.get_insert_block().unwrap(); // always positioned
+                            builder.build_unconditional_branch(scan_start)?;
+
+                            (memptr_value, scan_pred)
+                        };
+
+                    builder.position_at_end(scan_start);
+                    let idx = builder.build_phi(i64_type, "scan_idx_phi")?;
+                    let idx_value = idx.as_basic_value().into_int_value();
+
+                    let elptr = element(idx_value, "scan_loop")?;
+                    let value = builder
+                        .build_load(cell_type, elptr, "scan_value")?
+                        .into_int_value();
+                    let cmp = builder.build_int_compare(
+                        inkwell::IntPredicate::EQ,
+                        value,
+                        cell_type.const_zero(),
+                        "scan_cmp",
+                    )?;
+                    builder.build_conditional_branch(cmp, scan_exit, scan_body)?;
+
+                    builder.position_at_end(scan_body);
+                    if tape != TapePolicy::Wrap || fuel.is_some() || timed_out.is_some() {
+                        // leaving the tape stops the program where the scan got to
+                        builder.build_store(memptr, idx_value)?;
+                    }
+                    // a wrapping tape without a zero cell never stops
+                    if fuel.is_some() {
+                        // `steps` has the steps run before the scan, the scan itself needs one more
+                        let steps_value = builder
+                            .build_load(i64_type, steps, "scan_steps")?
+                            .into_int_value();
+                        let needed = builder.build_int_add(
+                            steps_value,
+                            i64_type.const_int(1, false),
+                            "scan_needed",
+                        )?;
+                        let fuel_value = builder
+                            .build_load(i64_type, fuel_left, "scan_fuel")?
+                            .into_int_value();
+                        let cmp = builder.build_int_compare(
+                            inkwell::IntPredicate::UGE,
+                            needed,
+                            fuel_value,
+                            "scan_fuel_cmp",
+                        )?;
+
+                        let fueled = context.append_basic_block(main_fn, "scan_fueled");
+                        builder.build_conditional_branch(cmp, out_of_fuel, fueled)?;
+                        builder.position_at_end(fueled);
+                        let fuel_value = builder.build_int_sub(
+                            fuel_value,
+                            i64_type.const_int(1, false),
+                            "scan_fuel_sub",
+                        )?;
+                        builder.build_store(fuel_left, fuel_value)?;
+                    }
+                    check_time("scan")?;
+                    let next = step(idx_value, n, "scan")?;
+                    // checking the next index can leave us in another block
+                    let scan_latch = builder.get_insert_block().unwrap(); // always positioned
+                    builder.build_unconditional_branch(scan_start)?;
+                    idx.add_incoming(&[(&scan_from, scan_pred), (&next, scan_latch)]);
+
+                    builder.position_at_end(scan_exit);
+                    builder.build_store(memptr, idx_value)?;
+                    builder.build_unconditional_branch(scan_end)?;
+
+                    builder.position_at_end(scan_end);
                 }
-                _ => unimplemented!(),
             }
         }
 
+        count(&mut uncounted)?;
+        save_state(0)?;
         builder.build_return(Some(&i64_type.const_zero()))?;
 
+        builder.position_at_end(eof_error);
+        let message = b"error: unexpected end of input\n";
+        let text = context.const_string(message, false);
+        let error = module.add_global(text.get_type(), None, "eof_error");
+        error.set_initializer(&text);
+        error.set_constant(true);
+        error.set_linkage(inkwell::module::Linkage::Private);
+        builder.build_call(
+            write_fn,
+            &[
+                i32_type.const_int(2, false).into(),
+                error.as_pointer_value().into(),
+                i64_type.const_int(message.len() as u64, false).into(),
+            ],
+            "write",
+        )?;
+        save_state(1)?;
+        builder.build_return(Some(&i64_type.const_int(1, false)))?;
+
+        builder.position_at_end(out_of_bounds);
+        let text =
+            context.const_string(b"error: pointer moved out of the tape to cell %lld\n", true);
+        let format = module.add_global(text.get_type(), None, "out_of_bounds");
+        format.set_initializer(&text);
+        format.set_constant(true);
+        format.set_linkage(inkwell::module::Linkage::Private);
+        let idx = builder.build_load(i64_type, out_of_bounds_idx, "out_of_bounds_idx")?;
+        builder.build_call(
+            dprintf_fn,
+            &[
+                i32_type.const_int(2, false).into(),
+                format.as_pointer_value().into(),
+                idx.into(),
+            ],
+            "dprintf",
+        )?;
+        save_state(2)?;
+        builder.build_return(Some(&i64_type.const_int(1, false)))?;
+
+        for (block, exit) in [(out_of_fuel, 3), (out_of_time, 4), (out_of_output, 5)] {
+            builder.position_at_end(block);
+            save_state(exit)?;
+            builder.build_return(Some(&i64_type.const_int(1, false)))?;
+        }
+
         Target::initialize_all(&inkwell::targets::InitializationConfig::default());
 
-        let target_triple = match ARGS.target {
-            Some(ref triple) => TargetTriple::create(triple),
-            None => TargetMachine::get_default_triple(),
-        };
         let cpu = match ARGS.target {
             Some(ref cpu) => cpu,
             None => &TargetMachine::get_host_cpu_name().to_string(),
@@ -330,15 +1140,72 @@ impl<const N: u64> Compiler<N> {
                 .create_jit_execution_engine(inkwell::OptimizationLevel::None)
                 .map_err(|_| eyre!("failed to create JIT execution engine"))?;
 
-            unsafe {
-                let func = engine
-                    .get_function::<unsafe extern "C" fn() -> i64>("main")
-                    .unwrap();
+            let timed_out = AtomicBool::new(false);
+            let mut machine = Machine {
+                memory: std::ptr::null(),
+                length: 0,
+                pointer: 0,
+                steps: 0,
+                exit: 0,
+                out_of_bounds: 0,
+                timed_out: &timed_out,
+            };
+            let (finished, done) = mpsc::channel::<()>();
+            std::thread::scope(|scope| {
+                if let Some(deadline) = self.deadline {
+                    let timed_out = &timed_out;
 
-                func.call();
-            }
+                    scope.spawn(move || {
+                        let timeout = deadline.saturating_duration_since(Instant::now());
 
-            return Ok(());
+                        if let Err(RecvTimeoutError::Timeout) = done.recv_timeout(timeout) {
+                            timed_out.store(true, Ordering::Relaxed);
+                        }
+                    });
+                }
+
+                unsafe {
+                    let func = engine
+                        .get_function::<unsafe extern "C" fn(*mut Machine) -> i64>("main")
+                        .unwrap();
+
+                    func.call(&mut machine);
+                }
+
+                // wakes up the watchdog, so the scope doesn't wait for the time to be up
+                drop(finished);
+            });
+
+            // the tape is never freed, the process is about to exit anyway
+            let memory = unsafe {
+                std::slice::from_raw_parts(machine.memory, (machine.length * bytes) as usize)
+            };
+            let exit = match machine.exit {
+                0 => ExitReason::Halted,
+                1 => ExitReason::Error("unexpected end of input".to_string()),
+                2 => ExitReason::Error(format!(
+                    "pointer moved out of the tape to cell {}",
+                    machine.out_of_bounds
+                )),
+                3 => ExitReason::LimitExceeded(Limit::Fuel),
+                4 => ExitReason::LimitExceeded(Limit::Timeout),
+                _ => ExitReason::LimitExceeded(Limit::Output),
+            };
+
+            return Ok(Some(MachineState {
+                tape: memory
+                    .chunks(bytes as usize)
+                    .map(|cell| {
+                        cell.iter()
+                            .rev()
+                            .fold(0, |value, &byte| value << 8 | byte as u32)
+                    })
+                    .collect(),
+                start: 0,
+                memptr: machine.pointer as isize,
+                steps: machine.steps,
+                exit: Some(exit),
+            }));
         }
 
         let source = ARGS
@@ -359,6 +1226,149 @@ impl<const N: u64> Compiler<N> {
                 .map_err(|_| eyre!("failed to write to write to output file"))?,
         };
 
-        Ok(())
+        Ok(None)
+    }
+
+    /// Builds the number of bytes `put_code_point` writes for `value`, as an `i64`.
+    fn build_encoded_length<'ctx>(
+        context: &'ctx Context,
+        builder: &Builder<'ctx>,
+        value: IntValue<'ctx>,
+    ) -> Result<IntValue<'ctx>> {
+        let i32_type = context.i32_type();
+        let i64_type = context.i64_type();
+
+        let value = builder.build_int_z_extend_or_bit_cast(value, i32_type, "out_length_value")?;
+
+        // anything past the last code point comes out as U+FFFD, which takes 3 bytes, the same
+        // as the surrogates that it replaces too
+        let mut length = i64_type.const_int(3, false);
+        for (below, bytes) in [(0x110000, 4), (0x10000, 3), (0x800, 2), (0x80, 1)] {
+            let cmp = builder.build_int_compare(
+                inkwell::IntPredicate::ULT,
+                value,
+                i32_type.const_int(below, false),
+                "out_length_cmp",
+            )?;
+
+            length = builder
+                .build_select(cmp, i64_type.const_int(bytes, false), length, "out_length")?
+                .into_int_value();
+        }
+
+        Ok(length)
+    }
+
+    /// Adds a function that writes a code point as UTF-8 using `putchar`, anything that isn't a
+    /// code point comes out as U+FFFD.
+    fn build_put_code_point<'ctx>(
+        context: &'ctx Context,
+        module: &Module<'ctx>,
+        putchar_fn: FunctionValue<'ctx>,
+    ) -> Result<FunctionValue<'ctx>> {
+        let builder = context.create_builder();
+        let i8_type = context.i8_type();
+        let i32_type = context.i32_type();
+
+        let fn_type = context.void_type().fn_type(&[i32_type.into()], false);
+        let function = module.add_function(
+            "put_code_point",
+            fn_type,
+            Some(inkwell::module::Linkage::Private),
+        );
+
+        let entry = context.append_basic_block(function, "entry");
+        builder.position_at_end(entry);
+
+        let value = function.get_first_param().unwrap().into_int_value(); // has one parameter
+        let surrogate = builder.build_and(
+            value,
+            i32_type.const_int(0xffff_f800, false),
+            "surrogate_bits",
+        )?;
+        let surrogate = builder.build_int_compare(
+            inkwell::IntPredicate::EQ,
+            surrogate,
+            i32_type.const_int(0xd800, false),
+            "surrogate",
+        )?;
+        let too_large = builder.build_int_compare(
+            inkwell::IntPredicate::UGT,
+            value,
+            i32_type.const_int(0x10_ffff, false),
+            "too_large",
+        )?;
+        let invalid = builder.build_or(surrogate, too_large, "invalid")?;
+        let value = builder
+            .build_select(
+                invalid,
+                i32_type.const_int(0xfffd, false),
+                value,
+                "code_point",
+            )?
+            .into_int_value();
+
+        // `bits` bits of the code point, from `shift` up, tagged with `tag`
+        let put = |shift: u64, bits: u64, tag: u64| -> Result<()> {
+            let byte = builder.build_right_shift(
+                value,
+                i32_type.const_int(shift, false),
+                false,
+                "shifted",
+            )?;
+            let byte =
+                builder.build_and(byte, i32_type.const_int((1 << bits) - 1, false), "bits")?;
+            let byte = builder.build_or(byte, i32_type.const_int(tag, false), "tagged")?;
+            let byte = builder.build_int_truncate(byte, i8_type, "byte")?;
+
+            builder.build_call(putchar_fn, &[byte.into()], "putchar")?;
+
+            Ok(())
+        };
+
+        // code points below `limit` take `length` bytes, the first one holds `bits` bits and is
+        // tagged with `tag`. the last encoding takes everything that's left
+        let encodings = [
+            (1, 7, 0x00, Some(0x80)),
+            (2, 5, 0xc0, Some(0x800)),
+            (3, 4, 0xe0, Some(0x1_0000)),
+            (4, 3, 0xf0, None),
+        ];
+        for (length, bits, tag, limit) in encodings {
+            let encode = context.append_basic_block(function, &format!("encode_{length}"));
+
+            let next = match limit {
+                Some(limit) => {
+                    let next = context.append_basic_block(function, "next");
+                    let cmp = builder.build_int_compare(
+                        inkwell::IntPredicate::ULT,
+                        value,
+                        i32_type.const_int(limit, false),
+                        &format!("fits_{length}"),
+                    )?;
+                    builder.build_conditional_branch(cmp, encode, next)?;
+
+                    Some(next)
+                }
+                None => {
+                    builder.build_unconditional_branch(encode)?;
+
+                    None
+                }
+            };
+
+            builder.position_at_end(encode);
+            put(6 * (length - 1), bits, tag)?;
+            for i in (0..length - 1).rev() {
+                put(6 * i, 6, 0x80)?;
+            }
+            builder.build_return(None)?;
+
+            if let Some(next) = next {
+                builder.position_at_end(next);
+            }
+        }
+
+        Ok(function)
     }
 }
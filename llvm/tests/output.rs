@@ -0,0 +1,23 @@
+//! Runs programs that print arbitrary bytes and checks exactly what comes out.
+
+#[path = "../../tests/support/mod.rs"]
+mod support;
+
+use support::{output, Backend};
+
+const BACKEND: Backend = Backend {
+    name: "llvm",
+    binary: env!("CARGO_BIN_EXE_llvm"),
+    args: &["--run"],
+    compiles: true,
+};
+
+#[test]
+fn bytes() {
+    output::bytes(&BACKEND);
+}
+
+#[test]
+fn unicode() {
+    output::unicode(&BACKEND);
+}
@@ -0,0 +1,33 @@
+//! Checks the tape, pointer and step count programs leave behind.
+
+#[path = "../../tests/support/mod.rs"]
+mod support;
+
+use support::{state, Backend};
+
+const BACKEND: Backend = Backend {
+    name: "llvm",
+    binary: env!("CARGO_BIN_EXE_llvm"),
+    args: &["--run"],
+    compiles: true,
+};
+
+#[test]
+fn halted() {
+    state::halted(&BACKEND);
+}
+
+#[test]
+fn error() {
+    state::error(&BACKEND);
+}
+
+#[test]
+fn limits() {
+    state::limits(&BACKEND);
+}
+
+#[test]
+fn scan() {
+    state::scan(&BACKEND);
+}
@@ -0,0 +1,72 @@
+use std::str::FromStr;
+
+/// Size of a single cell. Arithmetic wraps around, so a cell always holds a value modulo
+/// 2^bits: `-` on a zero cell gives 255, 65535 or 4294967295.
+///
+/// Only the low byte of a cell is printed, input bytes are stored as values from 0 to 255.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub enum CellWidth {
+    #[default]
+    U8,
+    U16,
+    U32,
+}
+
+impl CellWidth {
+    pub const fn bits(self) -> u32 {
+        match self {
+            Self::U8 => 8,
+            Self::U16 => 16,
+            Self::U32 => 32,
+        }
+    }
+
+    pub const fn bytes(self) -> u32 {
+        self.bits() / 8
+    }
+
+    /// Largest value a cell can hold.
+    pub const fn max(self) -> u32 {
+        u32::MAX >> (32 - self.bits())
+    }
+
+    /// What `value` from the IR ends up as in a cell.
+    pub const fn wrap(self, value: i32) -> u32 {
+        value as u32 & self.max()
+    }
+}
+
+impl FromStr for CellWidth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim_start_matches(['U', 'u']) {
+            "8" => Ok(Self::U8),
+            "16" => Ok(Self::U16),
+            "32" => Ok(Self::U32),
+            _ => Err(format!("unknown cell width '{s}', expected 8, 16 or 32")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap() {
+        assert_eq!(CellWidth::U8.wrap(-1), 255);
+        assert_eq!(CellWidth::U8.wrap(256 + 3), 3);
+        assert_eq!(CellWidth::U16.wrap(-2), 65534);
+        assert_eq!(CellWidth::U32.wrap(-1), u32::MAX);
+        assert_eq!(CellWidth::U32.wrap(7), 7);
+    }
+
+    #[test]
+    fn from_str() {
+        assert_eq!("8".parse(), Ok(CellWidth::U8));
+        assert_eq!("u16".parse(), Ok(CellWidth::U16));
+        assert_eq!("32".parse(), Ok(CellWidth::U32));
+        assert!("64".parse::<CellWidth>().is_err());
+    }
+}
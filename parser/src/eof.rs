@@ -0,0 +1,45 @@
+use std::str::FromStr;
+
+/// What `,` does once there is no more input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum EofPolicy {
+    /// Leaves the cell as it was.
+    Unchanged,
+    /// Stores 0.
+    #[default]
+    Zero,
+    /// Stores -1, i.e. the largest value a cell can hold.
+    MinusOne,
+    /// Stops the program with an error.
+    Error,
+}
+
+impl FromStr for EofPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unchanged" => Ok(Self::Unchanged),
+            "zero" | "0" => Ok(Self::Zero),
+            "minus-one" | "-1" => Ok(Self::MinusOne),
+            "error" => Ok(Self::Error),
+            _ => Err(format!(
+                "unknown EOF policy '{s}', expected unchanged, zero, minus-one or error"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_str() {
+        assert_eq!("unchanged".parse(), Ok(EofPolicy::Unchanged));
+        assert_eq!("0".parse(), Ok(EofPolicy::Zero));
+        assert_eq!("-1".parse(), Ok(EofPolicy::MinusOne));
+        assert_eq!("error".parse(), Ok(EofPolicy::Error));
+        assert!("ignore".parse::<EofPolicy>().is_err());
+    }
+}
@@ -1,4 +1,6 @@
-use std::fmt::Display;
+use std::fmt::{Display, Write as _};
+
+use crate::{LineIndex, Span};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum UnbalancedBrackets {
@@ -6,6 +8,28 @@ pub enum UnbalancedBrackets {
     UnopenedBracket(usize),
 }
 
+impl UnbalancedBrackets {
+    pub const fn position(&self) -> usize {
+        match *self {
+            Self::UnclosedBracket(pos) | Self::UnopenedBracket(pos) => pos,
+        }
+    }
+
+    pub const fn message(&self) -> &'static str {
+        match self {
+            Self::UnclosedBracket(_) => "unclosed '['",
+            Self::UnopenedBracket(_) => "unexpected ']'",
+        }
+    }
+
+    const fn code(&self) -> &'static str {
+        match self {
+            Self::UnclosedBracket(_) => "unclosed_bracket",
+            Self::UnopenedBracket(_) => "unopened_bracket",
+        }
+    }
+}
+
 impl Display for UnbalancedBrackets {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         use UnbalancedBrackets::*;
@@ -22,3 +46,202 @@ impl Display for UnbalancedBrackets {
 }
 
 impl std::error::Error for UnbalancedBrackets {}
+
+/// Single problem found in the source, along with the line it's on.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Diagnostic {
+    pub kind: UnbalancedBrackets,
+    pub span: Span,
+    pub source_line: String,
+}
+
+impl Diagnostic {
+    fn new(contents: &[u8], index: &LineIndex, kind: UnbalancedBrackets) -> Self {
+        let pos = kind.position();
+        let span = index.span(pos..pos + 1);
+        let source_line = &contents[index.line_range(contents, span.line)];
+
+        Self {
+            kind,
+            span,
+            source_line: String::from_utf8_lossy(source_line).into_owned(),
+        }
+    }
+
+    fn write_json(&self, out: &mut String) {
+        write!(
+            out,
+            r#"{{"kind":"{}","message":"{}","offset":{},"line":{},"column":{},"source_line":""#,
+            self.kind.code(),
+            self.kind.message(),
+            self.span.start,
+            self.span.line,
+            self.span.column,
+        )
+        .unwrap(); // writing to a `String` never fails
+        escape_json(&self.source_line, out);
+        out.push_str(r#""}"#);
+    }
+
+    // columns count bytes, but the line is displayed as characters
+    fn column_chars(&self) -> usize {
+        let mut bytes = 0;
+
+        self.source_line
+            .chars()
+            .take_while(|c| {
+                bytes += c.len_utf8();
+                bytes < self.span.column
+            })
+            .count()
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let line = self.span.line.to_string();
+        let gutter = " ".repeat(line.len());
+
+        // keep tabs so the caret lines up no matter how wide the terminal renders them
+        let caret_indent = self
+            .source_line
+            .chars()
+            .take(self.column_chars())
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+
+        writeln!(f, "error: {}", self.kind.message())?;
+        writeln!(f, "{gutter}--> {}", self.span)?;
+        writeln!(f, "{gutter} |")?;
+        writeln!(f, "{line} | {}", self.source_line)?;
+        write!(f, "{gutter} | {caret_indent}^")
+    }
+}
+
+/// Every unbalanced bracket in the source, in order of appearance.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ParseError {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl ParseError {
+    pub(crate) fn new(contents: &[u8], mut errors: Vec<UnbalancedBrackets>) -> Self {
+        let index = LineIndex::new(contents);
+        errors.sort_by_key(UnbalancedBrackets::position);
+
+        Self {
+            diagnostics: errors
+                .into_iter()
+                .map(|kind| Diagnostic::new(contents, &index, kind))
+                .collect(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut out = String::from(r#"{"errors":["#);
+
+        for (idx, diagnostic) in self.diagnostics.iter().enumerate() {
+            if idx != 0 {
+                out.push(',');
+            }
+
+            diagnostic.write_json(&mut out);
+        }
+        out.push_str("]}");
+
+        out
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (idx, diagnostic) in self.diagnostics.iter().enumerate() {
+            if idx != 0 {
+                writeln!(f)?;
+                writeln!(f)?;
+            }
+
+            write!(f, "{diagnostic}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Appends `value` to `out`, escaped to go between the quotes of a JSON string.
+pub fn escape_json(value: &str, out: &mut String) {
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str(r#"\""#),
+            '\\' => out.push_str(r"\\"),
+            '\n' => out.push_str(r"\n"),
+            '\r' => out.push_str(r"\r"),
+            '\t' => out.push_str(r"\t"),
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_caret() {
+        let error = ParseError::new(
+            b"+++\n++[-]]\n",
+            vec![UnbalancedBrackets::UnopenedBracket(9)],
+        );
+
+        assert_eq!(
+            error.to_string(),
+            "error: unexpected ']'\n --> 2:6\n  |\n2 | ++[-]]\n  |      ^"
+        );
+    }
+
+    #[test]
+    fn render_caret_tabs() {
+        let error = ParseError::new(b"\t\t[", vec![UnbalancedBrackets::UnclosedBracket(2)]);
+
+        assert_eq!(
+            error.to_string(),
+            "error: unclosed '['\n --> 1:3\n  |\n1 | \t\t[\n  | \t\t^"
+        );
+    }
+
+    #[test]
+    fn render_multiple_sorted() {
+        let error = ParseError::new(
+            b"[\n]]",
+            vec![
+                UnbalancedBrackets::UnopenedBracket(3),
+                UnbalancedBrackets::UnclosedBracket(0),
+            ],
+        );
+
+        assert_eq!(
+            error.diagnostics.iter().map(|d| d.kind).collect::<Vec<_>>(),
+            &[
+                UnbalancedBrackets::UnclosedBracket(0),
+                UnbalancedBrackets::UnopenedBracket(3)
+            ]
+        );
+        assert_eq!(
+            error.to_string(),
+            "error: unclosed '['\n --> 1:1\n  |\n1 | [\n  | ^\n\nerror: unexpected ']'\n --> 2:2\n  |\n2 | ]]\n  |  ^"
+        );
+    }
+
+    #[test]
+    fn json() {
+        let error = ParseError::new(b"\"\\ ]", vec![UnbalancedBrackets::UnopenedBracket(3)]);
+
+        assert_eq!(
+            error.to_json(),
+            r#"{"errors":[{"kind":"unopened_bracket","message":"unexpected ']'","offset":3,"line":1,"column":4,"source_line":"\"\\ ]"}]}"#
+        );
+    }
+}
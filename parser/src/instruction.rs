@@ -1,17 +1,41 @@
 use std::fmt::Display;
 
+/// Every instruction that touches a cell addresses it relative to the current pointer, so
+/// that pointer movement can be deferred. Without the `offsets` optimization it's always 0.
+///
+/// Values don't depend on the cell width, they are taken modulo 2^bits of the
+/// [`CellWidth`](crate::CellWidth) the program runs with. `-` is always `Add(0, -1)`.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Instruction {
-    Add(u8),
+    /// Adds value to the cell at offset.
+    Add(isize, i32),
     Move(isize),
-    In,
-    Out,
+    /// Reads into the cell at offset.
+    In(isize),
+    /// Writes the cell at offset.
+    Out(isize),
     JumpIfZero(usize),
     JumpIfNotZero(usize),
 
-    Clear,
-    Multiply(isize, u8),
+    /// Sets the cell at offset to 0.
+    Clear(isize),
+    /// Adds the cell at the first offset, multiplied by value, to the cell at the second one.
+    Multiply(isize, isize, i32),
     MoveUntilZero(isize),
+    /// Sets the cell at offset to value.
+    Set(isize, i32),
+}
+
+fn moves(count: isize) -> String {
+    let symbol = if count < 0 { "<" } else { ">" };
+
+    symbol.repeat(count.unsigned_abs())
+}
+
+fn adds(count: i32) -> String {
+    let symbol = if count < 0 { "-" } else { "+" };
+
+    symbol.repeat(count.unsigned_abs() as usize)
 }
 
 impl Display for Instruction {
@@ -19,41 +43,27 @@ impl Display for Instruction {
         use Instruction::*;
 
         match *self {
-            Add(count) => {
-                let symbol = if (count as i8) < 0 { "-" } else { "+" };
-
-                write!(
-                    f,
-                    "{}",
-                    symbol.repeat((count as i8).unsigned_abs() as usize)
-                )
-            }
-            Move(count) => {
-                let symbol = if count < 0 { "<" } else { ">" };
-
-                write!(f, "{}", symbol.repeat(count.unsigned_abs()))
-            }
-            In => write!(f, ","),
-            Out => write!(f, "."),
+            Add(offset, count) => write!(f, "{}{}{}", moves(offset), adds(count), moves(-offset)),
+            Move(count) => write!(f, "{}", moves(count)),
+            In(offset) => write!(f, "{},{}", moves(offset), moves(-offset)),
+            Out(offset) => write!(f, "{}.{}", moves(offset), moves(-offset)),
             JumpIfZero(_) => write!(f, "["),
             JumpIfNotZero(_) => write!(f, "]"),
-            Clear => write!(f, "[-]"),
-            Multiply(offset, mult) => {
-                let (symbol, symbol_opposite) = if offset < 0 { ("<", ">") } else { (">", "<") };
-                let add = if (mult as i8) < 0 { "-" } else { "+" };
-
-                write!(
-                    f,
-                    "[-{}{}{}]",
-                    symbol.repeat(offset.unsigned_abs()),
-                    add.repeat((mult as i8).unsigned_abs() as usize),
-                    symbol_opposite.repeat(offset.unsigned_abs()),
-                )
-            }
-            MoveUntilZero(count) => {
-                let symbol = if count < 0 { "<" } else { ">" };
-
-                write!(f, "[{}]", symbol.repeat(count.unsigned_abs()))
+            Clear(offset) => write!(f, "{}[-]{}", moves(offset), moves(-offset)),
+            // doesn't clear the current cell, so there's no brainfuck equivalent. this is
+            // basically one iteration of a balanced loop, done `value` times
+            Multiply(from, to, mult) => write!(
+                f,
+                "{}{{{}{}{}}}{}",
+                moves(from),
+                moves(to - from),
+                adds(mult),
+                moves(from - to),
+                moves(-from),
+            ),
+            MoveUntilZero(count) => write!(f, "[{}]", moves(count)),
+            Set(offset, count) => {
+                write!(f, "{}[-]{}{}", moves(offset), adds(count), moves(-offset))
             }
         }
     }
@@ -72,34 +82,34 @@ mod tests {
         };
     }
 
-    test!(display_add_positive, Add(10), "++++++++++");
-    test!(display_add_negative, Add(5u8.wrapping_neg()), "-----");
+    test!(display_add_positive, Add(0, 10), "++++++++++");
+    test!(display_add_negative, Add(0, -5), "-----");
     test!(display_move_positive, Move(12), ">>>>>>>>>>>>");
     test!(display_move_negative, Move(-7), "<<<<<<<");
-    test!(display_in, In, ",");
-    test!(display_out, Out, ".");
+    test!(display_in, In(0), ",");
+    test!(display_out, Out(0), ".");
     test!(display_jz, JumpIfZero(81), "[");
     test!(display_jne, JumpIfNotZero(1), "]");
-    test!(display_clear, Clear, "[-]");
+    test!(display_clear, Clear(0), "[-]");
     test!(
         display_multiply_positive_positive,
-        Multiply(5, 5),
-        "[->>>>>+++++<<<<<]"
+        Multiply(0, 5, 5),
+        "{>>>>>+++++<<<<<}"
     );
     test!(
         display_multiply_positive_negative,
-        Multiply(2, 3u8.wrapping_neg()),
-        "[->>---<<]"
+        Multiply(0, 2, -3),
+        "{>>---<<}"
     );
     test!(
         display_multiply_negative_positive,
-        Multiply(-7, 2),
-        "[-<<<<<<<++>>>>>>>]"
+        Multiply(0, -7, 2),
+        "{<<<<<<<++>>>>>>>}"
     );
     test!(
         display_multiply_negative_negative,
-        Multiply(-1, 4u8.wrapping_neg()),
-        "[-<---->]"
+        Multiply(0, -1, -4),
+        "{<---->}"
     );
     test!(display_move_until_zero_positive, MoveUntilZero(3), "[>>>]");
     test!(
@@ -107,4 +117,15 @@ mod tests {
         MoveUntilZero(-9),
         "[<<<<<<<<<]"
     );
+    test!(display_add_offset, Add(2, 3), ">>+++<<");
+    test!(display_in_offset, In(-1), "<,>");
+    test!(display_out_offset, Out(3), ">>>.<<<");
+    test!(display_clear_offset, Clear(-2), "<<[-]>>");
+    test!(
+        display_multiply_offset,
+        Multiply(2, -1, -2),
+        ">>{<<<-->>>}<<"
+    );
+    test!(display_set, Set(0, 3), "[-]+++");
+    test!(display_set_offset, Set(-1, -2), "<[-]-->");
 }
@@ -1,185 +1,115 @@
-pub use error::UnbalancedBrackets;
+pub use cell::CellWidth;
+pub use eof::EofPolicy;
+pub use error::{escape_json, Diagnostic, ParseError, UnbalancedBrackets};
 pub use instruction::Instruction;
 pub use opt::OptimizationOptions;
+pub use output::OutputMode;
+pub use pass::{dump, link_jumps, OptLevel, Pass, PassManager};
+pub use span::{LineIndex, Span, Spanned};
+pub use tape::{TapePolicy, DEFAULT_TAPE_SIZE};
 
+mod cell;
+mod eof;
 mod error;
 mod instruction;
 mod opt;
+mod output;
+pub mod pass;
+mod span;
+mod tape;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Parser<'a> {
     contents: &'a [u8],
-    idx: usize,
     instructions: Vec<Instruction>,
+    spans: Vec<usize>,
     jump_stack: Vec<(usize, usize)>,
+    errors: Vec<UnbalancedBrackets>,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(contents: &'a [u8]) -> Self {
         Self {
             contents,
-            idx: 0,
             instructions: Vec::new(),
+            spans: Vec::new(),
             jump_stack: Vec::new(),
+            errors: Vec::new(),
         }
     }
 
-    pub fn parse(
-        mut self,
+    pub fn parse(self, opts: OptimizationOptions) -> Result<Vec<Instruction>, ParseError> {
+        let instructions = self.parse_spanned(opts)?;
+
+        Ok(instructions.into_iter().map(|i| i.value).collect())
+    }
+
+    /// Same as [`Parser::parse`], but also returns which part of the source each instruction
+    /// came from. Folded instructions span everything they were folded from.
+    pub fn parse_spanned(
+        self,
         opts: OptimizationOptions,
-    ) -> Result<Vec<Instruction>, UnbalancedBrackets> {
-        while let Some(byte) = self.next() {
-            let instruction = match byte {
-                b'+' if opts.contract => self.parse_add(1),
-                b'+' => Instruction::Add(1),
-                b'-' if opts.contract => self.parse_add(1u8.wrapping_neg()),
-                b'-' => Instruction::Add(1u8.wrapping_neg()),
+    ) -> Result<Vec<Spanned<Instruction>>, ParseError> {
+        self.parse_with(&PassManager::from(opts))
+    }
+
+    pub fn parse_with(self, passes: &PassManager) -> Result<Vec<Spanned<Instruction>>, ParseError> {
+        Ok(passes.run(self.parse_raw()?))
+    }
 
-                b'>' if opts.contract => self.parse_move(1),
+    /// Parses every command into its own instruction, without running any passes.
+    pub fn parse_raw(mut self) -> Result<Vec<Spanned<Instruction>>, ParseError> {
+        for (pos, &byte) in self.contents.iter().enumerate() {
+            let instruction = match byte {
+                b'+' => Instruction::Add(0, 1),
+                b'-' => Instruction::Add(0, -1),
                 b'>' => Instruction::Move(1),
-                b'<' if opts.contract => self.parse_move(-1),
                 b'<' => Instruction::Move(-1),
-
-                b',' => Instruction::In,
-                b'.' => Instruction::Out,
-
+                b',' => Instruction::In(0),
+                b'.' => Instruction::Out(0),
                 b'[' => {
-                    self.jump_stack
-                        .push((self.instructions.len(), self.idx - 1));
+                    self.jump_stack.push((self.instructions.len(), pos));
 
                     Instruction::JumpIfZero(0)
                 }
                 b']' => {
                     if let Some((idx, _)) = self.jump_stack.pop() {
-                        if let Some(clear) = self.try_parse_clear(opts.clear) {
-                            clear
-                        } else if let Some(multiply) = self.try_parse_multiply(opts.multiply) {
-                            multiply
-                        } else if let Some(move_until) =
-                            self.try_parse_move_until_zero(opts.move_until_zero)
-                        {
-                            move_until
-                        } else {
-                            self.instructions[idx] =
-                                Instruction::JumpIfZero(self.instructions.len());
-
-                            Instruction::JumpIfNotZero(idx)
-                        }
+                        self.instructions[idx] = Instruction::JumpIfZero(self.instructions.len());
+
+                        Instruction::JumpIfNotZero(idx)
                     } else {
-                        return Err(UnbalancedBrackets::UnopenedBracket(self.idx - 1));
+                        // keep going, so that every unbalanced bracket is reported at once
+                        self.errors.push(UnbalancedBrackets::UnopenedBracket(pos));
+
+                        continue;
                     }
                 }
                 _ => continue,
             };
 
-            match instruction {
-                Instruction::Add(0) | Instruction::Move(0) => {}
-                _ => self.instructions.push(instruction),
-            }
-        }
-
-        if let Some((_, idx)) = self.jump_stack.pop() {
-            return Err(UnbalancedBrackets::UnclosedBracket(idx));
+            self.instructions.push(instruction);
+            self.spans.push(pos);
         }
 
-        Ok(self.instructions)
-    }
-
-    fn parse_add(&mut self, mut acc: u8) -> Instruction {
-        while let Some(byte) = self.peek() {
-            acc = match byte {
-                b'+' => acc.wrapping_add(1),
-                b'-' => acc.wrapping_sub(1),
-                b'>' | b'<' | b'.' | b',' | b'[' | b']' => break,
-                _ => acc,
-            };
+        self.errors.extend(
+            self.jump_stack
+                .drain(..)
+                .map(|(_, pos)| UnbalancedBrackets::UnclosedBracket(pos)),
+        );
 
-            self.idx += 1;
+        if !self.errors.is_empty() {
+            return Err(ParseError::new(self.contents, self.errors));
         }
 
-        Instruction::Add(acc)
-    }
-
-    fn parse_move(&mut self, mut acc: isize) -> Instruction {
-        while let Some(byte) = self.peek() {
-            match byte {
-                b'>' => acc += 1,
-                b'<' => acc -= 1,
-                b'+' | b'-' | b'.' | b',' | b'[' | b']' => break,
-                _ => {}
-            }
+        let index = LineIndex::new(self.contents);
+        let instructions = self
+            .instructions
+            .into_iter()
+            .zip(self.spans)
+            .map(|(instruction, pos)| Spanned::new(instruction, index.span(pos..pos + 1)))
+            .collect();
 
-            self.idx += 1;
-        }
-
-        Instruction::Move(acc)
-    }
-
-    fn try_parse_clear(&mut self, opt: bool) -> Option<Instruction> {
-        use Instruction::*;
-
-        if !opt {
-            return None;
-        }
-
-        match self.instructions.as_slice() {
-            [.., JumpIfZero(_), Add(n)] if n % 2 == 1 => {
-                self.remove_n(2);
-
-                Some(Clear)
-            }
-            _ => None,
-        }
-    }
-
-    fn try_parse_multiply(&mut self, opt: bool) -> Option<Instruction> {
-        use Instruction::*;
-
-        if !opt {
-            return None;
-        }
-
-        match self.instructions.as_slice() {
-            // 255 is actually -1
-            &[.., JumpIfZero(_), Add(255), Move(x), Add(n), Move(y)] if x.abs() - y.abs() == 0 => {
-                self.remove_n(5);
-
-                Some(Instruction::Multiply(x, n))
-            }
-            _ => None,
-        }
-    }
-
-    fn try_parse_move_until_zero(&mut self, opt: bool) -> Option<Instruction> {
-        use Instruction::*;
-
-        if !opt {
-            return None;
-        }
-
-        match self.instructions.as_slice() {
-            &[.., JumpIfZero(_), Move(n)] => {
-                self.remove_n(2);
-
-                Some(Instruction::MoveUntilZero(n))
-            }
-            _ => None,
-        }
-    }
-
-    fn remove_n(&mut self, count: usize) {
-        self.instructions.drain(self.instructions.len() - count..);
-    }
-
-    fn next(&mut self) -> Option<u8> {
-        let byte = self.peek();
-        self.idx += 1;
-
-        byte
-    }
-
-    fn peek(&mut self) -> Option<u8> {
-        self.contents.get(self.idx).copied()
+        Ok(instructions)
     }
 }
 
@@ -200,11 +130,27 @@ mod tests {
         };
     }
 
+    macro_rules! test_spanned {
+        ($name:ident($opts:expr), $input:expr => $output:expr) => {
+            #[test]
+            fn $name() {
+                let parser = Parser::new($input);
+                let result = parser.parse_spanned($opts).expect("failed to parse");
+                let result = result
+                    .into_iter()
+                    .map(|Spanned { value, span }| (value, span.range(), span.line, span.column))
+                    .collect::<Vec<_>>();
+
+                assert_eq!(result, $output)
+            }
+        };
+    }
+
     test!(parse_empty(OptimizationOptions::new()), b"" => &[]);
     test!(
         parse_add(OptimizationOptions::new().with_contract()),
         b"+++-+--++++----+++----+++--" =>
-        &[Add(1)]
+        &[Add(0, 1)]
     );
     test!(parse_zero_add(OptimizationOptions::new().with_contract()), b"+++--+++--+-++--+---" => &[]);
     test!(
@@ -213,8 +159,8 @@ mod tests {
         &[Move(4)]
     );
     test!(parse_zero_move(OptimizationOptions::new().with_contract()), b">>><<>>><<><>><<><<<" => &[]);
-    test!(parse_in(OptimizationOptions::new()), b",,,,,,,,,," => &[Instruction::In].repeat(10));
-    test!(parse_out(OptimizationOptions::new()), b".........." => &[Instruction::Out].repeat(10));
+    test!(parse_in(OptimizationOptions::new()), b",,,,,,,,,," => &[Instruction::In(0)].repeat(10));
+    test!(parse_out(OptimizationOptions::new()), b".........." => &[Instruction::Out(0)].repeat(10));
     test!(
         parse_jz_jnz(OptimizationOptions::new()),
         b"[[[[][]][[[]]]][[]]]" =>
@@ -233,21 +179,126 @@ mod tests {
     test!(
         parse_clear(OptimizationOptions::new().with_contract().with_clear()),
         b"[-][+++][--][+>+++-]" =>
-        &[Clear, Clear, JumpIfZero(4), Add(2u8.wrapping_neg()), JumpIfNotZero(2), JumpIfZero(9), Add(1), Move(1), Add(2), JumpIfNotZero(5)]
+        &[Clear(0), Clear(0), JumpIfZero(4), Add(0, -2), JumpIfNotZero(2), JumpIfZero(9), Add(0, 1), Move(1), Add(0, 2), JumpIfNotZero(5)]
     );
     test!(
         parse_multiply_1(OptimizationOptions::new().with_contract().with_multiply()),
         b"[->>>+<<<]" =>
-        &[Multiply(3, 1)]
+        &[Multiply(0, 3, 1), Clear(0)]
     );
     test!(
         parse_multiply_1_opposite(OptimizationOptions::new().with_contract().with_multiply()),
         b"[-<<<+>>>]" =>
-        &[Multiply(-3, 1)]
+        &[Multiply(0, -3, 1), Clear(0)]
+    );
+    test!(
+        parse_multiply_decrement_last(OptimizationOptions::new().with_contract().with_multiply()),
+        b"[>+<-]" =>
+        &[Multiply(0, 1, 1), Clear(0)]
+    );
+    test!(
+        parse_multiply_many(OptimizationOptions::new().with_contract().with_multiply()),
+        b"[->+>++<<]" =>
+        &[Multiply(0, 1, 1), Multiply(0, 2, 2), Clear(0)]
+    );
+    test!(
+        parse_multiply_many_scattered(OptimizationOptions::new().with_contract().with_multiply()),
+        b"[>++<<-->>+<-]" =>
+        &[Multiply(0, 1, 3), Multiply(0, -1, -2), Clear(0)]
+    );
+    test!(
+        parse_multiply_increment(OptimizationOptions::new().with_contract().with_multiply()),
+        b"[+>--<]" =>
+        &[Multiply(0, 1, 2), Clear(0)]
+    );
+    test!(
+        parse_multiply_uncontracted(OptimizationOptions::new().with_multiply()),
+        b"[->+>++<<]" =>
+        &[Multiply(0, 1, 1), Multiply(0, 2, 2), Clear(0)]
+    );
+    test!(
+        parse_multiply_unbalanced(OptimizationOptions::new().with_contract().with_multiply()),
+        b"[->>+>>]" =>
+        &[JumpIfZero(5), Add(0, -1), Move(2), Add(0, 1), Move(2), JumpIfNotZero(0)]
+    );
+    test!(
+        parse_multiply_bad_step(OptimizationOptions::new().with_contract().with_multiply()),
+        b"[-->+<]" =>
+        &[JumpIfZero(5), Add(0, -2), Move(1), Add(0, 1), Move(-1), JumpIfNotZero(0)]
+    );
+    test!(
+        parse_multiply_io(OptimizationOptions::new().with_contract().with_multiply()),
+        b"[->.+<]" =>
+        &[JumpIfZero(6), Add(0, -1), Move(1), Out(0), Add(0, 1), Move(-1), JumpIfNotZero(0)]
+    );
+    test!(
+        parse_offsets(OptimizationOptions::new().with_contract().with_offsets()),
+        b">+>+>+<<<" =>
+        &[Add(1, 1), Add(2, 1), Add(3, 1)]
+    );
+    test!(
+        parse_offsets_loop(OptimizationOptions::new().with_contract().with_offsets()),
+        b">+>.[<,>->]<<" =>
+        &[Add(1, 1), Out(2), Move(2), JumpIfZero(7), In(-1), Add(0, -1), Move(1), JumpIfNotZero(3), Move(-2)]
+    );
+    test!(
+        parse_offsets_folded(OptimizationOptions::all()),
+        b">>[-<+>]>[-]<<,[<]" =>
+        &[Multiply(2, 1, 1), Clear(2), Clear(3), In(1), Move(1), MoveUntilZero(-1)]
+    );
+    test!(
+        parse_dead_loops(OptimizationOptions::all()),
+        b"[comment, loop.]+[->+<]>>[.]<[->.<]" =>
+        &[Add(0, 1), Multiply(0, 1, 1), Clear(0), Move(1), JumpIfZero(7), Add(0, -1), Out(1), JumpIfNotZero(4)]
     );
     test!(
         parse_move_until_zero(OptimizationOptions::new().with_contract().with_move_until_zero()),
         b"[>>>][>][><><>>>>><>][>>>+<[>]]" =>
-        &[MoveUntilZero(3), MoveUntilZero(1), MoveUntilZero(5), JumpIfZero(8), Move(3), Add(1), Move(-1), MoveUntilZero(1), JumpIfNotZero(3)]
+        &[MoveUntilZero(3), MoveUntilZero(1), MoveUntilZero(5), JumpIfZero(8), Move(3), Add(0, 1), Move(-1), MoveUntilZero(1), JumpIfNotZero(3)]
+    );
+    #[test]
+    fn parse_fail_reports_all() {
+        let parser = Parser::new(b"]+[\n[-]]]\n[");
+        let error = parser
+            .parse(OptimizationOptions::all())
+            .expect_err("parsed unbalanced brackets");
+
+        assert_eq!(
+            error
+                .diagnostics
+                .iter()
+                .map(|d| (d.kind, d.span.line, d.span.column))
+                .collect::<Vec<_>>(),
+            &[
+                (UnbalancedBrackets::UnopenedBracket(0), 1, 1),
+                (UnbalancedBrackets::UnopenedBracket(8), 2, 5),
+                (UnbalancedBrackets::UnclosedBracket(10), 3, 1),
+            ]
+        );
+    }
+    test_spanned!(
+        parse_spanned_plain(OptimizationOptions::new()),
+        b"+>\n[.]" =>
+        &[(Add(0, 1), 0..1, 1, 1), (Move(1), 1..2, 1, 2), (JumpIfZero(4), 3..4, 2, 1), (Out(0), 4..5, 2, 2), (JumpIfNotZero(2), 5..6, 2, 3)]
+    );
+    test_spanned!(
+        parse_spanned_contract(OptimizationOptions::new().with_contract()),
+        b"++ comment +\n  >>\n<" =>
+        &[(Add(0, 3), 0..12, 1, 1), (Move(1), 15..19, 2, 3)]
+    );
+    test_spanned!(
+        parse_spanned_folded(OptimizationOptions::all()),
+        b"+[-]\n>[->>+<<]\n,[<]" =>
+        &[(Clear(0), 1..4, 1, 2), (Multiply(1, 3, 1), 6..14, 2, 2), (Clear(1), 6..14, 2, 2), (In(1), 15..16, 3, 1), (Move(1), 5..6, 2, 1), (MoveUntilZero(-1), 16..19, 3, 2)]
+    );
+    test!(
+        parse_set(OptimizationOptions::all()),
+        b"[-]+++++>>[-]--<<." =>
+        &[Set(0, 5), Set(2, -2), Out(0)]
+    );
+    test_spanned!(
+        parse_spanned_set(OptimizationOptions::all()),
+        b">[-]\n+++" =>
+        &[(Set(1, 3), 1..8, 1, 2), (Move(1), 0..1, 1, 1)]
     );
 }
@@ -4,6 +4,9 @@ pub struct OptimizationOptions {
     pub clear: bool,
     pub multiply: bool,
     pub move_until_zero: bool,
+    pub offsets: bool,
+    pub dead_loops: bool,
+    pub dead_stores: bool,
 }
 
 macro_rules! opt {
@@ -24,6 +27,9 @@ impl OptimizationOptions {
             clear: false,
             multiply: false,
             move_until_zero: false,
+            offsets: false,
+            dead_loops: false,
+            dead_stores: false,
         }
     }
 
@@ -33,6 +39,9 @@ impl OptimizationOptions {
             clear: true,
             multiply: true,
             move_until_zero: true,
+            offsets: true,
+            dead_loops: true,
+            dead_stores: true,
         }
     }
 
@@ -40,4 +49,7 @@ impl OptimizationOptions {
     opt!(clear, with_clear);
     opt!(multiply, with_multiply);
     opt!(move_until_zero, with_move_until_zero);
+    opt!(offsets, with_offsets);
+    opt!(dead_loops, with_dead_loops);
+    opt!(dead_stores, with_dead_stores);
 }
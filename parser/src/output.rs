@@ -0,0 +1,80 @@
+use std::str::FromStr;
+
+/// What `.` writes for a cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum OutputMode {
+    /// The low byte of the cell, exactly as it is.
+    #[default]
+    Bytes,
+    /// The cell as a Unicode code point encoded as UTF-8, mostly useful with 16- or 32-bit
+    /// cells. Values that aren't code points come out as U+FFFD.
+    Unicode,
+}
+
+impl OutputMode {
+    /// Bytes written for a cell holding `value`.
+    pub fn encode(self, value: u32, buffer: &mut [u8; 4]) -> &[u8] {
+        match self {
+            Self::Bytes => {
+                buffer[0] = value as u8;
+
+                &buffer[..1]
+            }
+            Self::Unicode => char::from_u32(value)
+                .unwrap_or(char::REPLACEMENT_CHARACTER)
+                .encode_utf8(buffer)
+                .as_bytes(),
+        }
+    }
+}
+
+impl FromStr for OutputMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bytes" => Ok(Self::Bytes),
+            "unicode" => Ok(Self::Unicode),
+            _ => Err(format!(
+                "unknown output mode '{s}', expected bytes or unicode"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode() {
+        let mut buffer = [0; 4];
+
+        assert_eq!(OutputMode::Bytes.encode(0xff, &mut buffer), &[0xff]);
+        assert_eq!(OutputMode::Bytes.encode(0x263a, &mut buffer), &[0x3a]);
+        assert_eq!(OutputMode::Unicode.encode(0x41, &mut buffer), b"A");
+        assert_eq!(
+            OutputMode::Unicode.encode(0xff, &mut buffer),
+            "ÿ".as_bytes()
+        );
+        assert_eq!(
+            OutputMode::Unicode.encode(0x263a, &mut buffer),
+            "☺".as_bytes()
+        );
+        assert_eq!(
+            OutputMode::Unicode.encode(0xd800, &mut buffer),
+            "\u{fffd}".as_bytes()
+        );
+        assert_eq!(
+            OutputMode::Unicode.encode(u32::MAX, &mut buffer),
+            "\u{fffd}".as_bytes()
+        );
+    }
+
+    #[test]
+    fn from_str() {
+        assert_eq!("bytes".parse(), Ok(OutputMode::Bytes));
+        assert_eq!("unicode".parse(), Ok(OutputMode::Unicode));
+        assert!("utf-16".parse::<OutputMode>().is_err());
+    }
+}
@@ -0,0 +1,356 @@
+use std::{fmt::Write as _, str::FromStr};
+
+use crate::{Instruction, OptimizationOptions, Spanned};
+
+pub use clear::ClearLoops;
+pub use contract::Contract;
+pub use dead_loops::DeadLoops;
+pub use dead_stores::DeadStores;
+pub use move_until_zero::ScanLoops;
+pub use multiply::BalancedLoops;
+pub use offsets::Offsets;
+
+mod clear;
+mod contract;
+mod dead_loops;
+mod dead_stores;
+mod move_until_zero;
+mod multiply;
+mod offsets;
+
+/// Transformation over the whole program.
+///
+/// Passes don't have to keep jump targets up to date, [`PassManager`] relinks them after every
+/// pass. They should however keep spans, so that results can be mapped back to the source.
+pub trait Pass: std::fmt::Debug {
+    fn name(&self) -> &'static str;
+
+    fn run(&self, instructions: Vec<Spanned<Instruction>>) -> Vec<Spanned<Instruction>>;
+}
+
+/// Runs passes in the order they were added. The same pass may be added more than once.
+#[derive(Debug, Default)]
+pub struct PassManager {
+    passes: Vec<Box<dyn Pass>>,
+}
+
+impl PassManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, pass: impl Pass + 'static) -> Self {
+        self.add(pass);
+
+        self
+    }
+
+    pub fn add(&mut self, pass: impl Pass + 'static) {
+        self.passes.push(Box::new(pass));
+    }
+
+    pub fn passes(&self) -> impl Iterator<Item = &dyn Pass> {
+        self.passes.iter().map(|pass| pass.as_ref())
+    }
+
+    pub fn run(&self, instructions: Vec<Spanned<Instruction>>) -> Vec<Spanned<Instruction>> {
+        self.run_inspect(instructions, |_, _| {})
+    }
+
+    /// Same as [`PassManager::run`], but calls `inspect` with the name of every pass and the
+    /// program right after it.
+    pub fn run_inspect(
+        &self,
+        mut instructions: Vec<Spanned<Instruction>>,
+        mut inspect: impl FnMut(&str, &[Spanned<Instruction>]),
+    ) -> Vec<Spanned<Instruction>> {
+        for pass in &self.passes {
+            instructions = pass.run(instructions);
+            link_jumps(&mut instructions);
+
+            inspect(pass.name(), &instructions);
+        }
+
+        instructions
+    }
+}
+
+impl From<OptimizationOptions> for PassManager {
+    fn from(opts: OptimizationOptions) -> Self {
+        let mut passes = Self::new();
+
+        if opts.contract {
+            passes.add(Contract);
+        }
+        if opts.clear {
+            passes.add(ClearLoops);
+        }
+        if opts.multiply {
+            passes.add(BalancedLoops);
+        }
+        if opts.move_until_zero {
+            passes.add(ScanLoops);
+        }
+        if opts.offsets {
+            passes.add(Offsets);
+        }
+        if opts.dead_loops {
+            passes.add(DeadLoops);
+        }
+        if opts.dead_stores {
+            passes.add(DeadStores);
+        }
+
+        passes
+    }
+}
+
+impl From<OptLevel> for PassManager {
+    fn from(level: OptLevel) -> Self {
+        Self::from(OptimizationOptions::from(level))
+    }
+}
+
+/// Named presets, from doing nothing at all to running every pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub enum OptLevel {
+    O0,
+    O1,
+    O2,
+    #[default]
+    O3,
+}
+
+impl From<OptLevel> for OptimizationOptions {
+    fn from(level: OptLevel) -> Self {
+        match level {
+            OptLevel::O0 => Self::new(),
+            OptLevel::O1 => Self::new().with_contract(),
+            OptLevel::O2 => Self::new()
+                .with_contract()
+                .with_clear()
+                .with_multiply()
+                .with_move_until_zero()
+                .with_dead_loops(),
+            OptLevel::O3 => Self::all(),
+        }
+    }
+}
+
+impl FromStr for OptLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim_start_matches(['O', 'o']) {
+            "0" => Ok(Self::O0),
+            "1" => Ok(Self::O1),
+            "2" => Ok(Self::O2),
+            "3" => Ok(Self::O3),
+            _ => Err(format!("unknown optimization level '{s}', expected 0 to 3")),
+        }
+    }
+}
+
+/// Recalculates jump targets after instructions were added or removed.
+///
+/// # Panics
+///
+/// Panics if brackets aren't balanced.
+pub fn link_jumps(instructions: &mut [Spanned<Instruction>]) {
+    use Instruction::*;
+
+    let mut stack = Vec::new();
+    for idx in 0..instructions.len() {
+        match instructions[idx].value {
+            JumpIfZero(_) => stack.push(idx),
+            JumpIfNotZero(_) => {
+                let start = stack.pop().expect("unbalanced brackets");
+
+                instructions[start].value = JumpIfZero(idx);
+                instructions[idx].value = JumpIfNotZero(start);
+            }
+            _ => {}
+        }
+    }
+
+    assert!(stack.is_empty(), "unbalanced brackets");
+}
+
+/// Renders the program one instruction per line, along with where it came from.
+pub fn dump(instructions: &[Spanned<Instruction>]) -> String {
+    let mut out = String::new();
+
+    for (idx, Spanned { value, span }) in instructions.iter().enumerate() {
+        let location = span.to_string();
+
+        // writing to a `String` never fails
+        writeln!(out, "{idx:>6}  {location:<10} {value:?}").unwrap();
+    }
+
+    out
+}
+
+/// Rebuilds the program innermost loop first, replacing every loop for which `fold` returns
+/// a replacement. `fold` gets the loop body, replacements span the whole loop.
+fn fold_loops(
+    instructions: Vec<Spanned<Instruction>>,
+    mut fold: impl FnMut(&[Instruction]) -> Option<Vec<Instruction>>,
+) -> Vec<Spanned<Instruction>> {
+    use Instruction::*;
+
+    let mut out: Vec<Spanned<Instruction>> = Vec::with_capacity(instructions.len());
+    let mut stack = Vec::new();
+
+    for instruction in instructions {
+        match instruction.value {
+            JumpIfZero(_) => {
+                stack.push(out.len());
+                out.push(instruction);
+            }
+            JumpIfNotZero(_) => {
+                let start = stack.pop().expect("unbalanced brackets");
+                let body = out[start + 1..].iter().map(|i| i.value).collect::<Vec<_>>();
+
+                match fold(&body) {
+                    Some(replacement) => {
+                        let span = out[start].span.to(&instruction.span);
+
+                        out.truncate(start);
+                        out.extend(replacement.into_iter().map(|i| Spanned::new(i, span)));
+                    }
+                    None => out.push(instruction),
+                }
+            }
+            _ => out.push(instruction),
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Instruction::*, Parser};
+
+    fn parse(source: &[u8], passes: &PassManager) -> Vec<Instruction> {
+        let instructions = Parser::new(source)
+            .parse_with(passes)
+            .expect("failed to parse");
+
+        instructions.into_iter().map(|i| i.value).collect()
+    }
+
+    #[test]
+    fn order_matters() {
+        let source = b"[+++]";
+
+        assert_eq!(
+            parse(source, &PassManager::new().with(ClearLoops).with(Contract)),
+            &[JumpIfZero(2), Add(0, 3), JumpIfNotZero(0)]
+        );
+        assert_eq!(
+            parse(source, &PassManager::new().with(Contract).with(ClearLoops)),
+            &[Clear(0)]
+        );
+    }
+
+    #[test]
+    fn same_pass_twice() {
+        let source = b"+>+<[-]>>";
+        let once = PassManager::new().with(Contract).with(ClearLoops);
+        let twice = PassManager::new()
+            .with(Contract)
+            .with(ClearLoops)
+            .with(Contract)
+            .with(ClearLoops);
+
+        assert_eq!(parse(source, &once), parse(source, &twice));
+    }
+
+    #[test]
+    fn presets() {
+        let source = b">>+++[-]<<[->+<]";
+
+        assert_eq!(parse(source, &OptLevel::O0.into()).len(), 16);
+        assert_eq!(
+            parse(source, &OptLevel::O1.into()),
+            &[
+                Move(2),
+                Add(0, 3),
+                JumpIfZero(4),
+                Add(0, -1),
+                JumpIfNotZero(2),
+                Move(-2),
+                JumpIfZero(11),
+                Add(0, -1),
+                Move(1),
+                Add(0, 1),
+                Move(-1),
+                JumpIfNotZero(6)
+            ]
+        );
+        assert_eq!(
+            parse(source, &OptLevel::O2.into()),
+            &[
+                Move(2),
+                Add(0, 3),
+                Clear(0),
+                Move(-2),
+                Multiply(0, 1, 1),
+                Clear(0)
+            ]
+        );
+        assert_eq!(
+            parse(source, &OptLevel::O3.into()),
+            &[Clear(2), Multiply(0, 1, 1), Clear(0)]
+        );
+    }
+
+    #[test]
+    fn opt_level_from_str() {
+        assert_eq!("0".parse(), Ok(OptLevel::O0));
+        assert_eq!("O2".parse(), Ok(OptLevel::O2));
+        assert!("4".parse::<OptLevel>().is_err());
+    }
+
+    #[test]
+    fn inspect_every_pass() {
+        let passes = PassManager::from(OptLevel::O3);
+        let mut names = Vec::new();
+
+        Parser::new(b"+")
+            .parse_raw()
+            .map(|instructions| {
+                passes.run_inspect(instructions, |name, _| names.push(name.to_owned()))
+            })
+            .expect("failed to parse");
+
+        assert_eq!(
+            names,
+            passes.passes().map(|pass| pass.name()).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            names,
+            &[
+                "contract",
+                "clear",
+                "multiply",
+                "move-until-zero",
+                "offsets",
+                "dead-loops",
+                "dead-stores"
+            ]
+        );
+    }
+
+    #[test]
+    fn dump_program() {
+        let instructions = Parser::new(b".\n [-]+").parse_spanned(OptLevel::O3.into());
+
+        assert_eq!(
+            dump(&instructions.expect("failed to parse")),
+            "     0  1:1        Out(0)\n     1  2:2        Set(0, 1)\n"
+        );
+    }
+}
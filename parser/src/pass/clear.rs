@@ -0,0 +1,21 @@
+use super::{fold_loops, Pass};
+use crate::{Instruction, Spanned};
+
+/// Replaces `[-]`, `[+++]` and other loops that can only end by wrapping around with `Clear`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ClearLoops;
+
+impl Pass for ClearLoops {
+    fn name(&self) -> &'static str {
+        "clear"
+    }
+
+    fn run(&self, instructions: Vec<Spanned<Instruction>>) -> Vec<Spanned<Instruction>> {
+        use Instruction::*;
+
+        fold_loops(instructions, |body| match *body {
+            [Add(0, n)] if n % 2 != 0 => Some(vec![Clear(0)]),
+            _ => None,
+        })
+    }
+}
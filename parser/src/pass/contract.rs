@@ -0,0 +1,40 @@
+use super::Pass;
+use crate::{Instruction, Spanned};
+
+/// Merges runs of `Add`s to the same cell and runs of `Move`s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Contract;
+
+impl Pass for Contract {
+    fn name(&self) -> &'static str {
+        "contract"
+    }
+
+    fn run(&self, instructions: Vec<Spanned<Instruction>>) -> Vec<Spanned<Instruction>> {
+        use Instruction::*;
+
+        let mut out: Vec<Spanned<Instruction>> = Vec::with_capacity(instructions.len());
+
+        for instruction in instructions {
+            let merged = match (out.last().map(|last| last.value), instruction.value) {
+                (Some(Add(at, n)), Add(offset, m)) if at == offset => Add(at, n.wrapping_add(m)),
+                (Some(Move(n)), Move(m)) => Move(n + m),
+                (_, Add(_, 0) | Move(0)) => continue,
+                _ => {
+                    out.push(instruction);
+
+                    continue;
+                }
+            };
+
+            let last = out.pop().unwrap(); // we've just matched on it
+
+            // if they cancel out, whatever came before might merge with the next one
+            if !matches!(merged, Add(_, 0) | Move(0)) {
+                out.push(Spanned::new(merged, last.span.to(&instruction.span)));
+            }
+        }
+
+        out
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use super::Pass;
+use crate::{Instruction, Spanned};
+
+/// Removes loops that start on a cell known to be zero, so their body can never run. Cells are
+/// known to be zero at the start of the program, which takes care of leading comment loops,
+/// right after a loop exits and after they were cleared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct DeadLoops;
+
+impl Pass for DeadLoops {
+    fn name(&self) -> &'static str {
+        "dead-loops"
+    }
+
+    fn run(&self, instructions: Vec<Spanned<Instruction>>) -> Vec<Spanned<Instruction>> {
+        use Instruction::*;
+
+        let mut out: Vec<Spanned<Instruction>> = Vec::with_capacity(instructions.len());
+        let mut cells = Cells::zeroed();
+        let mut instructions = instructions.into_iter();
+
+        while let Some(instruction) = instructions.next() {
+            match instruction.value {
+                JumpIfZero(_) if cells.is_zero(0) => {
+                    let mut depth = 1;
+                    for skipped in instructions.by_ref() {
+                        match skipped.value {
+                            JumpIfZero(_) => depth += 1,
+                            JumpIfNotZero(_) => depth -= 1,
+                            _ => {}
+                        }
+
+                        if depth == 0 {
+                            break;
+                        }
+                    }
+
+                    continue;
+                }
+                MoveUntilZero(_) if cells.is_zero(0) => continue,
+                // the body runs any number of times, nothing is known about it
+                JumpIfZero(_) => cells = Cells::unknown(),
+                JumpIfNotZero(_) | MoveUntilZero(_) => {
+                    cells = Cells::unknown();
+                    cells.set(0, true);
+                }
+                Move(n) => {
+                    cells.shift(n);
+
+                    // removed loops can leave movement on both sides of them
+                    if let Some(last) = out.last_mut() {
+                        if let Move(m) = last.value {
+                            if m + n == 0 {
+                                out.pop();
+                            } else {
+                                last.value = Move(m + n);
+                                last.span = last.span.to(&instruction.span);
+                            }
+
+                            continue;
+                        }
+                    }
+                }
+                Add(at, _) | In(at) => cells.set(at, false),
+                Clear(at) => cells.set(at, true),
+                Set(at, n) => cells.set(at, n == 0),
+                Multiply(from, to, _) => {
+                    if !cells.is_zero(from) {
+                        cells.set(to, false);
+                    }
+                }
+                Out(_) => {}
+            }
+
+            out.push(instruction);
+        }
+
+        out
+    }
+}
+
+/// What is known about cells, relative to the pointer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Cells {
+    /// Whether cells that aren't in `cells` are zero.
+    zero: bool,
+    cells: HashMap<isize, bool>,
+}
+
+impl Cells {
+    fn zeroed() -> Self {
+        Self {
+            zero: true,
+            cells: HashMap::new(),
+        }
+    }
+
+    fn unknown() -> Self {
+        Self {
+            zero: false,
+            cells: HashMap::new(),
+        }
+    }
+
+    fn is_zero(&self, offset: isize) -> bool {
+        self.cells.get(&offset).copied().unwrap_or(self.zero)
+    }
+
+    fn set(&mut self, offset: isize, zero: bool) {
+        self.cells.insert(offset, zero);
+    }
+
+    fn shift(&mut self, n: isize) {
+        self.cells = self
+            .cells
+            .drain()
+            .map(|(offset, zero)| (offset - n, zero))
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Instruction::*, Parser, PassManager};
+
+    fn run(source: &[u8]) -> Vec<Instruction> {
+        let passes = PassManager::new()
+            .with(super::super::Contract)
+            .with(super::super::ClearLoops)
+            .with(DeadLoops)
+            .with(super::super::Contract);
+        let instructions = Parser::new(source)
+            .parse_with(&passes)
+            .expect("failed to parse");
+
+        instructions.into_iter().map(|i| i.value).collect()
+    }
+
+    #[test]
+    fn leading_comment() {
+        assert_eq!(
+            run(b"[ comment, with [nested] loops. ]+."),
+            &[Add(0, 1), Out(0)]
+        );
+    }
+
+    #[test]
+    fn after_loop() {
+        assert_eq!(
+            run(b"+[.-][.][>]+"),
+            &[
+                Add(0, 1),
+                JumpIfZero(4),
+                Out(0),
+                Add(0, -1),
+                JumpIfNotZero(1),
+                Add(0, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn after_clear() {
+        assert_eq!(run(b",[-][.]>[.]<[.]"), &[In(0), Clear(0)]);
+    }
+
+    #[test]
+    fn merges_movement() {
+        assert_eq!(run(b",>>[.]<.<"), &[In(0), Move(1), Out(0), Move(-1)]);
+        assert_eq!(run(b",>[.]<"), &[In(0)]);
+    }
+
+    #[test]
+    fn unknown_cells() {
+        assert_eq!(
+            run(b",[.]>[.]<+[.]"),
+            &[
+                In(0),
+                JumpIfZero(3),
+                Out(0),
+                JumpIfNotZero(1),
+                Move(1),
+                JumpIfZero(7),
+                Out(0),
+                JumpIfNotZero(5),
+                Move(-1),
+                Add(0, 1),
+                JumpIfZero(12),
+                Out(0),
+                JumpIfNotZero(10)
+            ]
+        );
+    }
+}
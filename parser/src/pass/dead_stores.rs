@@ -0,0 +1,232 @@
+use super::Pass;
+use crate::{Instruction, Spanned};
+
+/// Folds `[-]+++` into `Set` and drops writes that are overwritten before anything reads them,
+/// as well as clears of a cell that is already zero because a loop just exited on it.
+///
+/// Only looks at straight-line code, anything that moves the pointer or jumps starts over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct DeadStores;
+
+impl Pass for DeadStores {
+    fn name(&self) -> &'static str {
+        "dead-stores"
+    }
+
+    fn run(&self, instructions: Vec<Spanned<Instruction>>) -> Vec<Spanned<Instruction>> {
+        use Instruction::*;
+
+        let mut out: Vec<Spanned<Instruction>> = Vec::with_capacity(instructions.len());
+        // where the current run of straight-line code starts in `out`
+        let mut block = 0;
+        // loops only ever exit on a zero cell
+        let mut exited_loop = false;
+
+        for instruction in instructions {
+            let Spanned { value, span } = instruction;
+
+            let cell = match value {
+                Add(at, _) | Clear(at) | Set(at, _) => at,
+                Move(_) | JumpIfZero(_) | JumpIfNotZero(_) | MoveUntilZero(_) => {
+                    out.push(instruction);
+                    block = out.len();
+                    exited_loop = matches!(value, JumpIfNotZero(_) | MoveUntilZero(_));
+
+                    continue;
+                }
+                In(_) | Out(_) | Multiply(..) => {
+                    out.push(instruction);
+
+                    continue;
+                }
+            };
+
+            let (last, known) = last_store(&out[block..], cell, exited_loop);
+
+            let value = match (value, known, last) {
+                (Add(_, n), Some(k), _) => set(cell, k.wrapping_add(n)),
+                (Add(_, n), None, Some(idx)) => {
+                    let idx = block + idx;
+
+                    if let Add(_, m) = out[idx].value {
+                        if m.wrapping_add(n) == 0 {
+                            out.remove(idx);
+                        } else {
+                            out[idx].value = Add(cell, m.wrapping_add(n));
+                            out[idx].span = out[idx].span.to(&span);
+                        }
+                    } else {
+                        out.push(instruction);
+                    }
+
+                    continue;
+                }
+                (Add(..), None, None) => {
+                    out.push(instruction);
+
+                    continue;
+                }
+                (Set(_, n), ..) => set(cell, n),
+                _ => value,
+            };
+            let target = match value {
+                Set(_, n) => n,
+                _ => 0,
+            };
+
+            // drop every store this one makes useless, an add that became a store covers
+            // whatever it was folded into
+            let mut span = span;
+            let mut known = known;
+            let mut last = last;
+            while let Some(idx) = last.filter(|&idx| overwritten(out[block + idx].value, cell)) {
+                if matches!(instruction.value, Add(..)) {
+                    span = out[block + idx].span.to(&span);
+                }
+
+                out.remove(block + idx);
+                (last, known) = last_store(&out[block..], cell, exited_loop);
+            }
+
+            if known != Some(target) {
+                out.push(Spanned::new(value, span));
+            }
+        }
+
+        out
+    }
+}
+
+/// Finds the last instruction in `block` that touches `cell`, along with the value it left the
+/// cell with if that's known.
+fn last_store(
+    block: &[Spanned<Instruction>],
+    cell: isize,
+    exited_loop: bool,
+) -> (Option<usize>, Option<i32>) {
+    use Instruction::*;
+
+    let last = block.iter().rposition(|i| touches(i.value, cell));
+    let known = match last.map(|idx| block[idx].value) {
+        Some(Clear(_)) => Some(0),
+        Some(Set(_, n)) => Some(n),
+        Some(_) => None,
+        None if exited_loop && cell == 0 => Some(0),
+        None => None,
+    };
+
+    (last, known)
+}
+
+fn set(at: isize, n: i32) -> Instruction {
+    match n {
+        0 => Instruction::Clear(at),
+        n => Instruction::Set(at, n),
+    }
+}
+
+fn touches(instruction: Instruction, cell: isize) -> bool {
+    use Instruction::*;
+
+    match instruction {
+        Add(at, _) | Clear(at) | Set(at, _) | In(at) | Out(at) => at == cell,
+        Multiply(from, to, _) => from == cell || to == cell,
+        Move(_) | JumpIfZero(_) | JumpIfNotZero(_) | MoveUntilZero(_) => false,
+    }
+}
+
+/// Whether storing to `cell` makes `instruction` useless. Input still has to be consumed.
+fn overwritten(instruction: Instruction, cell: isize) -> bool {
+    use Instruction::*;
+
+    match instruction {
+        Add(..) | Clear(_) | Set(..) => true,
+        Multiply(from, _, _) => from != cell,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Instruction::*, OptimizationOptions, Parser, PassManager};
+
+    fn run(source: &[u8]) -> Vec<Instruction> {
+        let passes = PassManager::from(
+            OptimizationOptions::new()
+                .with_contract()
+                .with_clear()
+                .with_multiply()
+                .with_move_until_zero()
+                .with_offsets()
+                .with_dead_stores(),
+        );
+        let instructions = Parser::new(source)
+            .parse_with(&passes)
+            .expect("failed to parse");
+
+        instructions.into_iter().map(|i| i.value).collect()
+    }
+
+    #[test]
+    fn clear_then_add() {
+        assert_eq!(run(b"[-]+++++"), &[Set(0, 5)]);
+        assert_eq!(run(b"[-]+++[-]--"), &[Set(0, -2)]);
+        assert_eq!(run(b"[-]+++---"), &[Clear(0)]);
+    }
+
+    #[test]
+    fn overwritten_stores() {
+        assert_eq!(run(b"+++[-]"), &[Clear(0)]);
+        assert_eq!(run(b">++<[-]>[-]<"), &[Clear(0), Clear(1)]);
+        assert_eq!(run(b"[->+<]>[-]"), &[Clear(0), Clear(1), Move(1)]);
+    }
+
+    #[test]
+    fn reads_keep_stores() {
+        assert_eq!(run(b"+++.[-]"), &[Add(0, 3), Out(0), Clear(0)]);
+        assert_eq!(run(b",[-]"), &[In(0), Clear(0)]);
+        assert_eq!(run(b"+++[->+<]"), &[Add(0, 3), Multiply(0, 1, 1), Clear(0)]);
+    }
+
+    #[test]
+    fn adds_across_other_cells() {
+        assert_eq!(run(b"+>+<+"), &[Add(0, 2), Add(1, 1)]);
+        assert_eq!(run(b"[-]>+<+"), &[Add(1, 1), Set(0, 1)]);
+        assert_eq!(run(b"+>+<-"), &[Add(1, 1)]);
+    }
+
+    #[test]
+    fn zero_after_loop() {
+        assert_eq!(run(b"[>]++"), &[MoveUntilZero(1), Set(0, 2)]);
+        assert_eq!(run(b"[>][-]"), &[MoveUntilZero(1)]);
+        assert_eq!(run(b"[>]++[-]"), &[MoveUntilZero(1)]);
+        assert_eq!(
+            run(b"[.-][-]+"),
+            &[
+                JumpIfZero(3),
+                Out(0),
+                Add(0, -1),
+                JumpIfNotZero(0),
+                Set(0, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn stops_at_pointer_movement() {
+        assert_eq!(run(b"+>-<[-]"), &[Add(1, -1), Clear(0)]);
+        assert_eq!(
+            run(b"+>[.]<[-]"),
+            &[
+                Add(0, 1),
+                Move(1),
+                JumpIfZero(4),
+                Out(0),
+                JumpIfNotZero(2),
+                Clear(-1),
+                Move(-1)
+            ]
+        );
+    }
+}
@@ -0,0 +1,21 @@
+use super::{fold_loops, Pass};
+use crate::{Instruction, Spanned};
+
+/// Replaces scan loops like `[>]` or `[<<<]` with `MoveUntilZero`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ScanLoops;
+
+impl Pass for ScanLoops {
+    fn name(&self) -> &'static str {
+        "move-until-zero"
+    }
+
+    fn run(&self, instructions: Vec<Spanned<Instruction>>) -> Vec<Spanned<Instruction>> {
+        use Instruction::*;
+
+        fold_loops(instructions, |body| match *body {
+            [Move(n)] => Some(vec![MoveUntilZero(n)]),
+            _ => None,
+        })
+    }
+}
@@ -0,0 +1,57 @@
+use super::{fold_loops, Pass};
+use crate::{Instruction, Spanned};
+
+/// Folds balanced loops, i.e. loops that only add and move, end up where they started and
+/// change the current cell by exactly 1 or -1 per iteration. Such a loop runs `value` (or
+/// `-value`) times, so every other cell it touches just gets a multiple of `value` added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct BalancedLoops;
+
+impl Pass for BalancedLoops {
+    fn name(&self) -> &'static str {
+        "multiply"
+    }
+
+    fn run(&self, instructions: Vec<Spanned<Instruction>>) -> Vec<Spanned<Instruction>> {
+        fold_loops(instructions, fold)
+    }
+}
+
+fn fold(body: &[Instruction]) -> Option<Vec<Instruction>> {
+    use Instruction::*;
+
+    let mut offset = 0isize;
+    let mut step = 0i32;
+    let mut targets: Vec<(isize, i32)> = Vec::new();
+
+    for instruction in body {
+        match *instruction {
+            Add(at, n) if offset + at == 0 => step = step.wrapping_add(n),
+            Add(at, n) => match targets.iter_mut().find(|(to, _)| *to == offset + at) {
+                Some((_, by)) => *by = by.wrapping_add(n),
+                None => targets.push((offset + at, n)),
+            },
+            Move(n) => offset += n,
+            _ => return None,
+        }
+    }
+
+    let negate = match step {
+        -1 => false,
+        1 => true,
+        _ => return None,
+    };
+
+    targets.retain(|&(_, by)| by != 0);
+    if offset != 0 || targets.is_empty() {
+        return None;
+    }
+
+    let multiply = targets.into_iter().map(|(to, by)| {
+        let by = if negate { by.wrapping_neg() } else { by };
+
+        Multiply(0, to, by)
+    });
+
+    Some(multiply.chain([Clear(0)]).collect())
+}
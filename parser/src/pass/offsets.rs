@@ -0,0 +1,59 @@
+use super::Pass;
+use crate::{Instruction, Span, Spanned};
+
+/// Instead of moving the pointer, attaches pending movement as an offset to every
+/// instruction that touches a cell. Movement is only done right before loop boundaries,
+/// where the pointer has to be exact, and at the end of the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Offsets;
+
+impl Pass for Offsets {
+    fn name(&self) -> &'static str {
+        "offsets"
+    }
+
+    fn run(&self, instructions: Vec<Spanned<Instruction>>) -> Vec<Spanned<Instruction>> {
+        use Instruction::*;
+
+        let mut out = Vec::with_capacity(instructions.len());
+        let mut pending: Option<(isize, Span)> = None;
+
+        for Spanned { value, span } in instructions {
+            let offset = pending.map_or(0, |(offset, _)| offset);
+
+            let instruction = match value {
+                Move(n) => {
+                    pending = Some(match pending {
+                        Some((offset, start)) => (offset + n, start.to(&span)),
+                        None => (n, span),
+                    });
+
+                    continue;
+                }
+                Add(at, n) => Add(at + offset, n),
+                In(at) => In(at + offset),
+                Out(at) => Out(at + offset),
+                Clear(at) => Clear(at + offset),
+                Set(at, n) => Set(at + offset, n),
+                Multiply(from, to, by) => Multiply(from + offset, to + offset, by),
+                JumpIfZero(_) | JumpIfNotZero(_) | MoveUntilZero(_) => {
+                    flush(&mut out, pending.take());
+
+                    value
+                }
+            };
+
+            out.push(Spanned::new(instruction, span));
+        }
+
+        flush(&mut out, pending);
+
+        out
+    }
+}
+
+fn flush(out: &mut Vec<Spanned<Instruction>>, pending: Option<(isize, Span)>) {
+    if let Some((offset, span)) = pending.filter(|&(offset, _)| offset != 0) {
+        out.push(Spanned::new(Instruction::Move(offset), span));
+    }
+}
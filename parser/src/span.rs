@@ -0,0 +1,146 @@
+use std::{fmt::Display, ops::Range};
+
+/// Region of the source an instruction was parsed from.
+///
+/// `start..end` is a byte range, `line` and `column` are 1-based and point at `start`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub const fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    pub const fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub const fn contains(&self, offset: usize) -> bool {
+        self.start <= offset && offset < self.end
+    }
+
+    /// Span from the start of `self` to the end of `other`.
+    pub const fn to(&self, other: &Span) -> Span {
+        Span {
+            end: other.end,
+            ..*self
+        }
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Spanned<T> {
+    pub value: T,
+    pub span: Span,
+}
+
+impl<T> Spanned<T> {
+    pub const fn new(value: T, span: Span) -> Self {
+        Self { value, span }
+    }
+}
+
+/// Maps byte offsets in the source to lines and columns.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(contents: &[u8]) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(
+                contents
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &byte)| byte == b'\n')
+                    .map(|(idx, _)| idx + 1),
+            )
+            .collect();
+
+        Self { line_starts }
+    }
+
+    /// Returns 1-based line and column of `offset`.
+    pub fn location(&self, offset: usize) -> (usize, usize) {
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+
+        (line, offset - self.line_starts[line - 1] + 1)
+    }
+
+    pub fn span(&self, range: Range<usize>) -> Span {
+        let (line, column) = self.location(range.start);
+
+        Span {
+            start: range.start,
+            end: range.end,
+            line,
+            column,
+        }
+    }
+
+    /// Returns byte range of 1-based `line`, without the trailing newline.
+    pub fn line_range(&self, contents: &[u8], line: usize) -> Range<usize> {
+        let start = self.line_starts[line - 1];
+        let end = self
+            .line_starts
+            .get(line)
+            .map_or(contents.len(), |&next| next - 1);
+
+        start..end
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &[u8] = b"+++\n>>[-]\n\n.";
+
+    #[test]
+    fn location_first_line() {
+        let index = LineIndex::new(SOURCE);
+
+        assert_eq!(index.location(0), (1, 1));
+        assert_eq!(index.location(2), (1, 3));
+    }
+
+    #[test]
+    fn location_after_newline() {
+        let index = LineIndex::new(SOURCE);
+
+        assert_eq!(index.location(3), (1, 4));
+        assert_eq!(index.location(4), (2, 1));
+        assert_eq!(index.location(6), (2, 3));
+        assert_eq!(index.location(11), (4, 1));
+    }
+
+    #[test]
+    fn line_range() {
+        let index = LineIndex::new(SOURCE);
+
+        assert_eq!(index.line_count(), 4);
+        assert_eq!(&SOURCE[index.line_range(SOURCE, 2)], b">>[-]");
+        assert_eq!(&SOURCE[index.line_range(SOURCE, 3)], b"");
+        assert_eq!(&SOURCE[index.line_range(SOURCE, 4)], b".");
+    }
+}
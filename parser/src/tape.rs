@@ -0,0 +1,46 @@
+use std::str::FromStr;
+
+/// Number of cells programs get unless asked otherwise.
+pub const DEFAULT_TAPE_SIZE: usize = 30_000;
+
+/// What happens when the pointer leaves the tape, either by moving or by touching a cell at
+/// an offset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TapePolicy {
+    /// Continues from the other end.
+    #[default]
+    Wrap,
+    /// Stops the program with an error that says where the pointer went.
+    Error,
+    /// Makes the tape longer. The interpreter grows it both ways, compiled programs only to the
+    /// right, so going left of the first cell is still an error there.
+    Grow,
+}
+
+impl FromStr for TapePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wrap" => Ok(Self::Wrap),
+            "error" => Ok(Self::Error),
+            "grow" => Ok(Self::Grow),
+            _ => Err(format!(
+                "unknown tape policy '{s}', expected wrap, error or grow"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_str() {
+        assert_eq!("wrap".parse(), Ok(TapePolicy::Wrap));
+        assert_eq!("error".parse(), Ok(TapePolicy::Error));
+        assert_eq!("grow".parse(), Ok(TapePolicy::Grow));
+        assert!("clamp".parse::<TapePolicy>().is_err());
+    }
+}
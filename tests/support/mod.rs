@@ -0,0 +1,68 @@
+//! What the tests of every backend share: the programs they run, what they have to print or
+//! leave behind and a way to run them with any of the binaries.
+
+// every test only uses part of it
+#![allow(dead_code)]
+
+use std::{
+    fmt::Debug,
+    path::Path,
+    process::{Command, Output},
+};
+
+pub mod output;
+pub mod state;
+
+/// A binary to run programs with.
+#[derive(Debug, Clone, Copy)]
+pub struct Backend {
+    /// What to call its temporary files and itself when it fails.
+    pub name: &'static str,
+    pub binary: &'static str,
+    /// Passed before any other argument.
+    pub args: &'static [&'static str],
+    /// Whether it takes `--eval-budget`, so programs run once as a whole in generated code and
+    /// once with as much as possible evaluated at compile time.
+    pub compiles: bool,
+}
+
+impl Backend {
+    /// Runs `source` with `args` and takes what `check` makes of how it went. If it compiles,
+    /// that is once with each budget and both have to come out the same.
+    pub fn run<T: PartialEq + Debug>(
+        &self,
+        name: &str,
+        source: &str,
+        args: &[&str],
+        check: impl Fn(Output) -> T,
+    ) -> T {
+        let budgets: &[&[&str]] = if self.compiles {
+            &[&["--eval-budget", "0"], &["--eval-budget", "1000000"]]
+        } else {
+            &[&[]]
+        };
+        let mut results = budgets
+            .iter()
+            .map(|budget| check(self.run_once(name, source, &[args, budget].concat())));
+
+        let first = results.next().expect("there is always a budget");
+        for result in results {
+            assert_eq!(result, first);
+        }
+
+        first
+    }
+
+    /// Runs `source` a single time with `args`.
+    pub fn run_once(&self, name: &str, source: &str, args: &[&str]) -> Output {
+        let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{}-{name}.b", self.name));
+        std::fs::write(&path, source).expect("failed to write source");
+
+        Command::new(self.binary)
+            .args(self.args)
+            .args(args)
+            .arg(&path)
+            .output()
+            .unwrap_or_else(|err| panic!("failed to run {}: {err}", self.name))
+    }
+}
@@ -0,0 +1,65 @@
+//! Programs that print arbitrary bytes and exactly what has to come out.
+
+use super::Backend;
+
+/// Prints 0, 255 and 128.
+fn binary() -> String {
+    format!(".-.{}.", "-".repeat(127))
+}
+
+/// Prints 9786, which is U+263A.
+fn smiley() -> String {
+    format!(
+        "{}[>{}<-]>{}.",
+        "+".repeat(99),
+        "+".repeat(98),
+        "+".repeat(84)
+    )
+}
+
+fn run(backend: &Backend, name: &str, source: &str, args: &[&str]) -> Vec<u8> {
+    backend.run(name, source, args, |output| {
+        assert!(
+            output.status.success(),
+            "{} failed: {output:?}",
+            backend.name
+        );
+
+        output.stdout
+    })
+}
+
+pub fn bytes(backend: &Backend) {
+    assert_eq!(run(backend, "bytes", &binary(), &[]), [0x00, 0xff, 0x80]);
+    assert_eq!(
+        run(backend, "bytes-u16", &smiley(), &["--cell-width", "16"]),
+        [0x3a]
+    );
+}
+
+pub fn unicode(backend: &Backend) {
+    let unicode = ["--output-mode", "unicode"];
+
+    assert_eq!(
+        run(backend, "unicode", &binary(), &unicode),
+        "\0ÿ\u{80}".as_bytes()
+    );
+    assert_eq!(
+        run(
+            backend,
+            "unicode-u16",
+            &smiley(),
+            &[&unicode[..], &["--cell-width", "16"]].concat()
+        ),
+        "☺".as_bytes()
+    );
+    assert_eq!(
+        run(
+            backend,
+            "unicode-u32",
+            "-.",
+            &[&unicode[..], &["--cell-width", "32"]].concat()
+        ),
+        "\u{fffd}".as_bytes()
+    );
+}
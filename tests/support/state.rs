@@ -0,0 +1,152 @@
+//! Programs and the tape, pointer and step count they have to leave behind.
+
+use super::Backend;
+
+/// Runs `source` with `--dump-state` and takes the state it printed.
+fn state(backend: &Backend, name: &str, source: &str, args: &[&str]) -> String {
+    let args = [&["--dump-state", "--tape-size", "4"], args].concat();
+
+    backend.run(&format!("state-{name}"), source, &args, |output| {
+        String::from_utf8(output.stderr)
+            .expect("stderr is not UTF-8")
+            .lines()
+            .next()
+            .expect("no state was printed")
+            .to_string()
+    })
+}
+
+pub fn halted(backend: &Backend) {
+    assert_eq!(
+        state(backend, "halted", "++>+++[<+>-]<", &[]),
+        r#"{"tape":[5,0,0,0],"start":0,"memptr":0,"steps":4,"exit":{"reason":"halted"}}"#
+    );
+    assert_eq!(
+        state(backend, "grown", ">>>>>+<", &["--tape-policy", "grow"]),
+        r#"{"tape":[0,0,0,0,0,1,0,0],"start":0,"memptr":4,"steps":2,"exit":{"reason":"halted"}}"#
+    );
+}
+
+pub fn error(backend: &Backend) {
+    assert_eq!(
+        state(
+            backend,
+            "out-of-bounds",
+            "+>>>>>+",
+            &["--tape-policy", "error"]
+        ),
+        r#"{"tape":[1,0,0,0],"start":0,"memptr":0,"steps":1,"exit":{"reason":"error","message":"pointer moved out of the tape to cell 5"}}"#
+    );
+    assert_eq!(
+        state(backend, "eof", "+>,", &["--eof", "error"]),
+        r#"{"tape":[1,0,0,0],"start":0,"memptr":0,"steps":1,"exit":{"reason":"error","message":"unexpected end of input"}}"#
+    );
+}
+
+pub fn limits(backend: &Backend) {
+    assert_eq!(
+        state(backend, "fuel", "+[>+<]", &["--fuel", "10"]),
+        r#"{"tape":[1,4,0,0],"start":0,"memptr":0,"steps":10,"exit":{"reason":"limit_exceeded","limit":"fuel"}}"#
+    );
+    // a wrapping tape without a zero cell, the scan would never stop without fuel
+    assert_eq!(
+        state(backend, "fuel-scan", "+>+>+>+<<<[>]", &["--fuel", "100"]),
+        r#"{"tape":[1,1,1,1],"start":0,"memptr":3,"steps":4,"exit":{"reason":"limit_exceeded","limit":"fuel"}}"#
+    );
+    assert_eq!(
+        state(backend, "output", "+[.+]", &["--max-output", "3"]),
+        r#"{"tape":[4,0,0,0],"start":0,"memptr":0,"steps":11,"exit":{"reason":"limit_exceeded","limit":"output"}}"#
+    );
+
+    // how far it gets isn't up to the program, so this only runs once
+    let output = backend.run_once(
+        "state-timeout",
+        "+[]",
+        &["--dump-state", "--timeout", "0.1"],
+    );
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).expect("stderr is not UTF-8");
+    assert!(stderr
+        .lines()
+        .next()
+        .expect("no state was printed")
+        .ends_with(r#""exit":{"reason":"limit_exceeded","limit":"timeout"}}"#));
+}
+
+/// `[>]` from the first cell, with the zero on the last cell and with no zero at all, which
+/// finds nothing on the fast path and has to carry on from the last cell. `[<]` from the last
+/// cell the same way, carrying on from the first.
+pub fn scan(backend: &Backend) {
+    for policy in ["wrap", "error", "grow"] {
+        assert_eq!(
+            state(
+                backend,
+                &format!("scan-last-{policy}"),
+                "+>+>+<<[>]",
+                &["--tape-policy", policy]
+            ),
+            r#"{"tape":[1,1,1,0],"start":0,"memptr":3,"steps":4,"exit":{"reason":"halted"}}"#
+        );
+    }
+
+    assert_eq!(
+        state(
+            backend,
+            "scan-none-error",
+            "+>+>+>+<<<[>]",
+            &["--tape-policy", "error"]
+        ),
+        r#"{"tape":[1,1,1,1],"start":0,"memptr":3,"steps":4,"exit":{"reason":"error","message":"pointer moved out of the tape to cell 4"}}"#
+    );
+    assert_eq!(
+        state(
+            backend,
+            "scan-none-grow",
+            "+>+>+>+<<<[>]",
+            &["--tape-policy", "grow"]
+        ),
+        r#"{"tape":[1,1,1,1,0,0,0,0],"start":0,"memptr":4,"steps":5,"exit":{"reason":"halted"}}"#
+    );
+
+    for policy in ["wrap", "error", "grow"] {
+        assert_eq!(
+            state(
+                backend,
+                &format!("scan-back-first-{policy}"),
+                ">+>+>+[<]",
+                &["--tape-policy", policy]
+            ),
+            r#"{"tape":[0,1,1,1],"start":0,"memptr":0,"steps":5,"exit":{"reason":"halted"}}"#
+        );
+    }
+    assert_eq!(
+        state(
+            backend,
+            "scan-back-none-error",
+            "+>+>+>+[<]",
+            &["--tape-policy", "error"]
+        ),
+        r#"{"tape":[1,1,1,1],"start":0,"memptr":0,"steps":5,"exit":{"reason":"error","message":"pointer moved out of the tape to cell -1"}}"#
+    );
+
+    // a wrapping tape without a zero goes around until it runs out of time, which isn't up to
+    // the program either, and only generated code scans
+    let output = backend.run_once(
+        "state-scan-none-wrap",
+        "+>+>+>+<<<[>]",
+        &[
+            "--dump-state",
+            "--tape-size",
+            "4",
+            "--timeout",
+            "0.1",
+            "--eval-budget",
+            "0",
+        ],
+    );
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).expect("stderr is not UTF-8");
+    let state = stderr.lines().next().expect("no state was printed");
+    assert!(state.starts_with(r#"{"tape":[1,1,1,1],"#));
+    assert!(state.ends_with(r#""exit":{"reason":"limit_exceeded","limit":"timeout"}}"#));
+}